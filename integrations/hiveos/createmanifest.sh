@@ -39,4 +39,7 @@ CUSTOM_CONFIG_FILENAME=/hive/miners/custom/\$CUSTOM_NAME/config.ini
 CUSTOM_LOG_BASENAME=/var/log/miner/\$CUSTOM_NAME
 
 WEB_PORT=3338
+
+# Local port of the miner's JSON API, used by h-stats.sh
+API_PORT=4110
 EOF
\ No newline at end of file
@@ -12,6 +12,8 @@
 [[ -e /hive/miners/custom ]] && . /hive/miners/custom/karlsen-miner/h-manifest.conf
 conf=""
 conf+=" --karlsend-address=$CUSTOM_URL --mining-address $CUSTOM_TEMPLATE"
+# h-stats.sh reads the port back from the config, a user config may bind the API elsewhere
+[[ $CUSTOM_USER_CONFIG != *--api-bind* ]] && conf+=" --api-bind 127.0.0.1:${API_PORT:-4110}"
 
 
 [[ ! -z $CUSTOM_USER_CONFIG ]] && conf+=" $CUSTOM_USER_CONFIG"
@@ -14,22 +14,39 @@
 #######################
 
 . /hive/miners/custom/karlsen-miner/h-manifest.conf
-stats_raw=`cat $CUSTOM_LOG_BASENAME.log | grep -w "hashrate" | tail -n 1 `
-#echo $stats_raw
-
-#Calculate miner log freshness
-
+# The port the miner was started with, from --api-bind in its config
+api_port=`grep -oP -- '--api-bind[= ]\S+:\K\d+' $CUSTOM_CONFIG_FILENAME 2>/dev/null | tail -n 1`
+api_url=http://127.0.0.1:${api_port:-${API_PORT:-4110}}
 maxDelay=120
-time_now=`date +%s`
-datetime_rep=`echo $stats_raw | awk '{print $1}' | awk -F[ '{print $2}'`
-time_rep=`date -d $datetime_rep +%s`
-diffTime=`echo $((time_now-time_rep)) | tr -d '-'`
+
+# Prefer the miner's JSON API, fall back to the log for miners started without --api-bind
+api_summary=`curl -s --max-time 2 $api_url/summary`
+if [[ -n $api_summary ]]; then
+        stats_raw=$api_summary
+        api_devices=`curl -s --max-time 2 $api_url/devices`
+        #Seconds since the last job, a miner without one is reported as stale
+        diffTime=`curl -s --max-time 2 $api_url/job | jq '.current.age_secs // 1000000000 | floor'`
+        [[ -z $diffTime ]] && diffTime=$maxDelay
+else
+        stats_raw=`cat $CUSTOM_LOG_BASENAME.log | grep -w "hashrate" | tail -n 1 `
+        #echo $stats_raw
+
+        #Calculate miner log freshness
+        time_now=`date +%s`
+        datetime_rep=`echo $stats_raw | awk '{print $1}' | awk -F[ '{print $2}'`
+        time_rep=`date -d $datetime_rep +%s`
+        diffTime=`echo $((time_now-time_rep)) | tr -d '-'`
+fi
 
 if [ "$diffTime" -lt "$maxDelay" ]; then
-        total_hashrate=`echo $stats_raw | awk '{print $7}' | cut -d "." -f 1,2 --output-delimiter='' | sed 's/$/0/'`
-	if [[ $stats_raw == *"Ghash"* ]]; then
-		total_hashrate=$(($total_hashrate*1000))
-	fi
+        if [[ -n $api_summary ]]; then
+                total_hashrate=`echo $api_summary | jq '.hashrate / 1000 | floor'`
+        else
+                total_hashrate=`echo $stats_raw | awk '{print $7}' | cut -d "." -f 1,2 --output-delimiter='' | sed 's/$/0/'`
+                if [[ $stats_raw == *"Ghash"* ]]; then
+                        total_hashrate=$(($total_hashrate*1000))
+                fi
+        fi
 
         #GPU Status
         gpu_stats=$(< $GPU_STATS_JSON)
@@ -61,10 +78,15 @@ if [ "$diffTime" -lt "$maxDelay" ]; then
                 busid_arr+=($((16#${BASH_REMATCH[1]})))
                 temp_arr+=(${temps[i]})
                 fan_arr+=(${fans[i]})                
-                gpu_raw=`cat $CUSTOM_LOG_BASENAME.log | grep -w "Device #"$i | tail -n 1 `
-                hashrate=`echo $gpu_raw | awk '{print $(NF-1)}' | cut -d "." -f 1,2 --output-delimiter='' | sed 's/$/0/'`
-                if [[ $gpu_raw == *"Ghash"* ]]; then
-                        hashrate=$(($hashrate*1000))
+                if [[ -n $api_summary ]]; then
+                        #Devices are listed in launch order, match them by their "#N (name)" id like the log does
+                        hashrate=`echo $api_devices | jq --argjson i $i '[.[] | select(.id | startswith("#\\($i) "))][0].hashrate // 0 | . / 1000 | floor'`
+                else
+                        gpu_raw=`cat $CUSTOM_LOG_BASENAME.log | grep -w "Device #"$i | tail -n 1 `
+                        hashrate=`echo $gpu_raw | awk '{print $(NF-1)}' | cut -d "." -f 1,2 --output-delimiter='' | sed 's/$/0/'`
+                        if [[ $gpu_raw == *"Ghash"* ]]; then
+                                hashrate=$(($hashrate*1000))
+                        fi
                 fi
                 hash_arr+=($hashrate)		
         done
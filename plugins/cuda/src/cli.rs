@@ -5,6 +5,7 @@ use std::str::FromStr;
 pub enum NonceGenEnum {
     Lean,
     Xoshiro,
+    Sequential,
 }
 
 impl FromStr for NonceGenEnum {
@@ -14,6 +15,7 @@ impl FromStr for NonceGenEnum {
         match s.to_lowercase().as_str() {
             "lean" => Ok(Self::Lean),
             "xoshiro" => Ok(Self::Xoshiro),
+            "sequential" => Ok(Self::Sequential),
             _ => Err("Unknown string".into()),
         }
     }
@@ -51,7 +53,7 @@ pub struct CudaOpt {
     pub cuda_no_blocking_sync: bool,
     #[clap(
         long = "cuda-nonce-gen",
-        help = "The random method used to generate nonces. Options: (i) xoshiro - each thread in GPU will have its own random state, creating a (pseudo-)independent xoshiro sequence (ii) lean - each GPU will have a single random nonce, and each GPU thread will work on nonce + thread id.",
+        help = "The random method used to generate nonces. Options: (i) xoshiro - each thread in GPU will have its own random state, creating a (pseudo-)independent xoshiro sequence (ii) lean - each GPU will have a single random nonce, and each GPU thread will work on nonce + thread id (iii) sequential - each GPU walks its part of the nonce space in order, never trying a nonce twice for the same job.",
         default_value = "lean"
     )]
     pub cuda_nonce_gen: NonceGenEnum,
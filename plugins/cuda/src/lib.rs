@@ -12,6 +12,7 @@ use {
     nvml_wrapper::enums::device::GpuLockedClocksSetting,
     nvml_wrapper::Device as NvmlDevice,
     nvml_wrapper::Nvml,
+    std::sync::Arc,
 };
 
 pub type Error = Box<dyn StdError + Send + Sync + 'static>;
@@ -26,8 +27,9 @@ const DEFAULT_WORKLOAD_SCALE: f32 = 8.;
 
 pub struct CudaPlugin {
     specs: Vec<CudaWorkerSpec>,
+    /// Shared with the workers for their telemetry, NVML is initialised once per process
     #[cfg(feature = "overclock")]
-    nvml_instance: Nvml,
+    nvml_instance: Arc<Nvml>,
     _enabled: bool,
 }
 
@@ -39,7 +41,7 @@ impl CudaPlugin {
             specs: Vec::new(),
             _enabled: false,
             #[cfg(feature = "overclock")]
-            nvml_instance: Nvml::init()?,
+            nvml_instance: Arc::new(Nvml::init()?),
         })
     }
 }
@@ -54,7 +56,10 @@ impl Plugin for CudaPlugin {
     }
 
     fn get_worker_specs(&self) -> Vec<Box<dyn WorkerSpec>> {
-        self.specs.iter().map(|spec| Box::new(*spec) as Box<dyn WorkerSpec>).collect::<Vec<Box<dyn WorkerSpec>>>()
+        self.specs
+            .iter()
+            .map(|spec| Box::new(spec.clone()) as Box<dyn WorkerSpec>)
+            .collect::<Vec<Box<dyn WorkerSpec>>>()
     }
 
     //noinspection RsTypeCheck
@@ -135,6 +140,8 @@ impl Plugin for CudaPlugin {
                     is_absolute: opts.cuda_workload_absolute,
                     blocking_sync: !opts.cuda_no_blocking_sync,
                     random: opts.cuda_nonce_gen,
+                    #[cfg(feature = "overclock")]
+                    nvml: self.nvml_instance.clone(),
                 })
                 .collect();
         }
@@ -142,13 +149,15 @@ impl Plugin for CudaPlugin {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 struct CudaWorkerSpec {
     device_id: u32,
     workload: f32,
     is_absolute: bool,
     blocking_sync: bool,
     random: NonceGenEnum,
+    #[cfg(feature = "overclock")]
+    nvml: Arc<Nvml>,
 }
 
 impl WorkerSpec for CudaWorkerSpec {
@@ -158,10 +167,12 @@ impl WorkerSpec for CudaWorkerSpec {
     }
 
     fn build(&self) -> Box<dyn Worker> {
-        Box::new(
+        let worker =
             CudaGPUWorker::new(self.device_id, self.workload, self.is_absolute, self.blocking_sync, self.random)
-                .unwrap(),
-        )
+                .unwrap();
+        #[cfg(feature = "overclock")]
+        let worker = worker.with_nvml(self.nvml.clone());
+        Box::new(worker)
     }
 }
 
@@ -13,6 +13,11 @@ use std::ffi::CString;
 use std::ops::BitXor;
 use std::sync::{Arc, Weak};
 use tiny_keccak::Hasher;
+#[cfg(feature = "overclock")]
+use {
+    nvml_wrapper::enum_wrappers::device::{Clock, TemperatureSensor},
+    nvml_wrapper::Nvml,
+};
 
 static BPS: f32 = 1.0;
 
@@ -191,6 +196,10 @@ pub struct CudaGPUWorker {
     _context: Context,
 
     random: NonceGenEnum,
+    /// Next nonce to start from in sequential mode
+    sequence: u64,
+    #[cfg(feature = "overclock")]
+    nvml: Option<Arc<Nvml>>,
 }
 
 impl Worker for CudaGPUWorker {
@@ -217,6 +226,12 @@ impl Worker for CudaGPUWorker {
                 0
             }
             NonceGenEnum::Xoshiro => 1,
+            NonceGenEnum::Sequential => {
+                // The kernel XORs the thread id in, so keep the low bits clear
+                self.rand_state.copy_from(&[self.sequence]).unwrap();
+                self.sequence = self.sequence.wrapping_add(self.workload.next_power_of_two() as u64);
+                0
+            }
         };
 
         self.start_event.record(stream).unwrap();
@@ -255,11 +270,40 @@ impl Worker for CudaGPUWorker {
         self.workload
     }
 
+    fn set_workload(&mut self, workload: usize) -> Result<(), Error> {
+        // Xoshiro keeps a state per thread, more threads need more states
+        if self.random == NonceGenEnum::Xoshiro && self.rand_state.len() < 4 * workload {
+            self.rand_state = xoshiro_states(self.device_id, workload)?;
+        }
+        self.khashv2_kernel.set_workload(workload.try_into()?);
+        self.workload = workload;
+        Ok(())
+    }
+
     #[inline(always)]
     fn copy_output_to(&mut self, nonces: &mut Vec<u64>) -> Result<(), Error> {
         self.final_nonce_buff.copy_to(nonces)?;
         Ok(())
     }
+
+    #[cfg(feature = "overclock")]
+    fn telemetry(&self) -> Vec<(&'static str, f64)> {
+        let device = match self.nvml.as_ref().map(|nvml| nvml.device_by_index(self.device_id)) {
+            Some(Ok(device)) => device,
+            _ => return Vec::new(),
+        };
+        // Not every card reports everything, e.g. passively cooled ones have no fan
+        [
+            ("temperature_celsius", device.temperature(TemperatureSensor::Gpu).map(|t| t as f64)),
+            ("power_watts", device.power_usage().map(|mw| mw as f64 / 1000.0)),
+            ("fan_speed_percent", device.fan_speed(0).map(|f| f as f64)),
+            ("core_clock_mhz", device.clock_info(Clock::Graphics).map(|c| c as f64)),
+            ("memory_clock_mhz", device.clock_info(Clock::Memory).map(|c| c as f64)),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value.ok()?)))
+        .collect()
+    }
 }
 
 pub fn keccak_in_place(data: &mut [u8]) {
@@ -344,6 +388,19 @@ fn build_dataset_gpu(
     Ok(())
 }
 
+/// One independent xoshiro state per GPU thread
+fn xoshiro_states(device_id: u32, workload: usize) -> Result<DeviceBuffer<u64>, Error> {
+    let mut buffer = DeviceBuffer::<u64>::zeroed(4 * workload)?;
+    info!("GPU #{} is generating initial seed. This may take some time.", device_id);
+    let mut seed = [1u64; 4];
+    seed.try_fill(&mut rand::thread_rng())?;
+    buffer.copy_from(
+        Xoshiro256StarStar::new(&seed).iter_jump_state().take(workload).flatten().collect::<Vec<u64>>().as_slice(),
+    )?;
+    info!("GPU #{} initialized", device_id);
+    Ok(buffer)
+}
+
 impl CudaGPUWorker {
     pub fn new(
         device_id: u32,
@@ -427,20 +484,7 @@ impl CudaGPUWorker {
         let rand_state: DeviceBuffer<u64> = match random {
             NonceGenEnum::Xoshiro => {
                 info!("Using xoshiro for nonce-generation");
-                let mut buffer = DeviceBuffer::<u64>::zeroed(4 * (chosen_workload as usize)).unwrap();
-                info!("GPU #{} is generating initial seed. This may take some time.", device_id);
-                let mut seed = [1u64; 4];
-                seed.try_fill(&mut rand::thread_rng())?;
-                buffer.copy_from(
-                    Xoshiro256StarStar::new(&seed)
-                        .iter_jump_state()
-                        .take(chosen_workload as usize)
-                        .flatten()
-                        .collect::<Vec<u64>>()
-                        .as_slice(),
-                )?;
-                info!("GPU #{} initialized", device_id);
-                buffer
+                xoshiro_states(device_id, chosen_workload as usize)?
             }
             NonceGenEnum::Lean => {
                 info!("Using lean nonce-generation");
@@ -449,6 +493,10 @@ impl CudaGPUWorker {
                 buffer.copy_from(&[seed])?;
                 buffer
             }
+            NonceGenEnum::Sequential => {
+                info!("Using sequential nonce-generation");
+                DeviceBuffer::<u64>::zeroed(1).unwrap()
+            }
         };
         Ok(Self {
             device_id,
@@ -464,6 +512,16 @@ impl CudaGPUWorker {
             dataset2,
             khashv2_kernel,
             random,
+            sequence: 0,
+            #[cfg(feature = "overclock")]
+            nvml: None,
         })
     }
+
+    /// Reads telemetry through the plugin's NVML handle
+    #[cfg(feature = "overclock")]
+    pub fn with_nvml(mut self, nvml: Arc<Nvml>) -> Self {
+        self.nvml = Some(nvml);
+        self
+    }
 }
@@ -0,0 +1,101 @@
+//! Read-only HTTP API with the miner's statistics as JSON and Prometheus metrics, enabled with `--api-bind`
+
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use log::info;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+use crate::metrics;
+use crate::stats::Statistics;
+use crate::Error;
+
+pub async fn serve(bind: SocketAddr, statistics: Arc<Statistics>) -> Result<(), Error> {
+    let listener = TcpListener::bind(bind).await?;
+    info!("API listening on http://{}", listener.local_addr()?);
+    axum::serve(listener, router(statistics)).await?;
+    Ok(())
+}
+
+fn router(statistics: Arc<Statistics>) -> Router {
+    Router::new()
+        .route("/summary", get(|State(stats): State<Arc<Statistics>>| async move { json(&stats.summary()) }))
+        .route("/devices", get(|State(stats): State<Arc<Statistics>>| async move { json(&stats.devices()) }))
+        .route("/shares", get(|State(stats): State<Arc<Statistics>>| async move { json(&stats.snapshot()) }))
+        .route("/blocks", get(|State(stats): State<Arc<Statistics>>| async move { json(&stats.blocks()) }))
+        .route("/job", get(|State(stats): State<Arc<Statistics>>| async move { json(&stats.job()) }))
+        .route(
+            "/metrics",
+            get(|State(stats): State<Arc<Statistics>>| async move {
+                ([(CONTENT_TYPE, metrics::CONTENT_TYPE)], metrics::render(&stats))
+            }),
+        )
+        .with_state(statistics)
+}
+
+pub fn json<T: Serialize>(value: &T) -> Response {
+    match serde_json::to_string(value) {
+        Ok(body) => ([(CONTENT_TYPE, "application/json")], body).into_response(),
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::router;
+    use crate::stats::Statistics;
+    use serde_json::Value;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    async fn get(address: std::net::SocketAddr, path: &str) -> Value {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+        assert!(head.to_lowercase().contains("content-type: application/json"), "{}", head);
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_api() {
+        let statistics = Arc::new(Statistics::default());
+        statistics.add_device("#0 (Test GPU)");
+        statistics.device_hashes("#0 (Test GPU)", 3000, Duration::from_secs(3));
+        statistics.block_found("ab".repeat(32));
+        statistics.new_job(Some("7".into()), Default::default(), u64::MAX, 0);
+        let _connection = statistics.connected("stratum+tcp://pool:5555");
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let router = router(statistics.clone());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let summary = get(address, "/summary").await;
+        assert_eq!(summary["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(summary["connections"][0], "pool:5555");
+        assert_eq!(summary["hashrate"], 1000.0);
+        assert_eq!(summary["blocks_found"], 1);
+        assert_eq!(get(address, "/devices").await[0]["hashes"], 3000);
+        assert_eq!(get(address, "/blocks").await["recent"][0]["hash"], "ab".repeat(32));
+        let job = get(address, "/job").await;
+        assert_eq!((job["jobs"].as_u64(), job["current"]["id"].as_str()), (Some(1), Some("7")));
+        assert_eq!(get(address, "/shares").await["lifetime"]["accepted"], 0);
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.contains("karlsen_miner_blocks_found_total 1"), "{}", response);
+    }
+}
@@ -0,0 +1,238 @@
+//! `karlsen-miner benchmark`: measures the hashrate on synthetic jobs, without a pool or node. The jobs go
+//! through the same `MinerManager` and workers as real ones, so the results compare across driver and clock settings.
+
+use clap::Parser;
+use karlsen_miner::PluginManager;
+use log::{info, warn};
+use rand::{rng, RngCore};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+use crate::client::stratum::difficulty_to_target;
+use crate::miner::{JobSink, MinerManager};
+use crate::pow::BlockSeed;
+use crate::stats::Statistics;
+use crate::target::Uint256;
+use crate::Error;
+
+/// A new job as often as Karlsen blocks come, so the workers switch jobs as they do when mining
+const JOB_RATE: Duration = Duration::from_secs(1);
+const WAITING_LOG_RATE: Duration = Duration::from_secs(30);
+
+#[derive(Parser, Debug)]
+#[clap(
+    name = "karlsen-miner benchmark",
+    bin_name = "karlsen-miner benchmark",
+    version,
+    about = "Measures the hashrate on synthetic jobs, without a pool or node",
+    term_width = 0
+)]
+pub struct BenchmarkOpt {
+    #[clap(short, long, help = "Enable debug logging level")]
+    pub debug: bool,
+
+    #[clap(long = "duration", default_value = "60", help = "Seconds to measure for, after the warmup")]
+    pub duration: u64,
+
+    #[clap(
+        long = "warmup",
+        default_value = "10",
+        help = "Seconds every device hashes before measuring, for clocks and temperatures to settle"
+    )]
+    pub warmup: u64,
+
+    #[clap(long = "difficulty", default_value = "1", help = "Share difficulty of the synthetic jobs")]
+    pub difficulty: f64,
+
+    #[clap(long = "json", help = "Print the results to stdout as JSON")]
+    pub json: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub duration_secs: f64,
+    pub difficulty: f64,
+    pub devices: Vec<Measurement>,
+    pub total: Measurement,
+    /// Shares the workers found while measuring
+    pub shares: u64,
+}
+
+/// Hashes per second over the one second samples taken while measuring
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Measurement {
+    pub device: String,
+    pub hashrate: f64,
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub samples: usize,
+}
+
+impl Measurement {
+    fn new(device: &str, rates: &[f64]) -> Self {
+        let count = rates.len().max(1) as f64;
+        let hashrate = rates.iter().sum::<f64>() / count;
+        let variance = rates.iter().map(|rate| (rate - hashrate).powi(2)).sum::<f64>() / (count - 1.0).max(1.0);
+        Self {
+            device: device.to_string(),
+            hashrate,
+            stddev: variance.sqrt(),
+            min: rates.iter().copied().reduce(f64::min).unwrap_or_default(),
+            max: rates.iter().copied().reduce(f64::max).unwrap_or_default(),
+            samples: rates.len(),
+        }
+    }
+
+    fn log(&self) {
+        let (rate, suffix) = MinerManager::hash_suffix(self.hashrate);
+        let spread = match self.hashrate > 0.0 {
+            true => self.stddev / self.hashrate * 100.0,
+            false => 0.0,
+        };
+        info!("{}: {:.2} {} ± {:.1}% over {} samples", self.device, rate, suffix, spread, self.samples);
+    }
+}
+
+fn synthetic_job(id: u64, target: Uint256) -> BlockSeed {
+    let mut rng = rng();
+    BlockSeed::PartialBlock {
+        id: id.to_string(),
+        header_hash: [rng.next_u64(), rng.next_u64(), rng.next_u64(), rng.next_u64()],
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64,
+        nonce: 0,
+        target,
+        nonce_mask: u64::MAX,
+        nonce_fixed: 0,
+        hash: None,
+    }
+}
+
+pub async fn run(opt: &BenchmarkOpt, plugin_manager: &PluginManager) -> Result<Report, Error> {
+    let target = difficulty_to_target(opt.difficulty)?;
+    let statistics = Arc::new(Statistics::default());
+    let (share_channel, mut shares) = mpsc::channel(1);
+    let mut miner_manager = MinerManager::new(share_channel, plugin_manager, statistics.clone());
+    let devices = statistics.devices().into_iter().map(|device| device.id).collect::<Vec<_>>();
+    if devices.is_empty() {
+        return Err("No GPU workers specified".into());
+    }
+
+    let mut ticker = tokio::time::interval(JOB_RATE);
+    let mut jobs = 0;
+    let started = Instant::now();
+    let mut waiting_logged = started;
+    // Building the DAG takes a while, the warmup starts once every device hashes
+    let mut hashing = None::<Instant>;
+    let mut measuring = None::<Instant>;
+    // Per device and then in total: the samples so far, and when the last one was taken
+    let mut rates = vec![(Vec::new(), started); devices.len() + 1];
+    let mut found = 0;
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            Some(_) = shares.recv() => {
+                found += measuring.is_some() as u64;
+                continue;
+            }
+        }
+        miner_manager.process_block(Some(synthetic_job(jobs, target))).await?;
+        jobs += 1;
+
+        let now = Instant::now();
+        let Some(hashing_started) = hashing else {
+            let hashed =
+                |device: &String| statistics.sampled_rates(Some(device), started).iter().any(|(_, rate)| *rate > 0.0);
+            let idle = devices.iter().filter(|device| !hashed(device));
+            match idle.count() {
+                0 => {
+                    info!("All devices are hashing, warming up for {}s", opt.warmup);
+                    hashing = Some(now);
+                }
+                idle if now - waiting_logged >= WAITING_LOG_RATE => {
+                    info!("Waiting for {} devices to start hashing", idle);
+                    waiting_logged = now;
+                }
+                _ => {}
+            }
+            continue;
+        };
+        if measuring.is_none() && now - hashing_started >= Duration::from_secs(opt.warmup) {
+            info!("Measuring for {}s", opt.duration);
+            measuring = Some(now);
+            rates.iter_mut().for_each(|(_, since)| *since = now);
+        }
+        let Some(measuring_started) = measuring else { continue };
+
+        // Collected as they come, the statistics only keep the last minutes of samples
+        for (device, (samples, since)) in
+            devices.iter().map(|device| Some(device.as_str())).chain([None]).zip(&mut rates)
+        {
+            for (at, rate) in statistics.sampled_rates(device, *since) {
+                samples.push(rate);
+                *since = at;
+            }
+        }
+        if now - measuring_started >= Duration::from_secs(opt.duration) {
+            break;
+        }
+    }
+    let duration = measuring.map(|measuring| measuring.elapsed()).unwrap_or_default();
+    drop(miner_manager);
+
+    let report = Report {
+        duration_secs: duration.as_secs_f64(),
+        difficulty: opt.difficulty,
+        devices: devices.iter().zip(&rates).map(|(device, (samples, _))| Measurement::new(device, samples)).collect(),
+        total: Measurement::new("Total", &rates[devices.len()].0),
+        shares: found,
+    };
+    if report.devices.iter().any(|device| device.samples == 0) {
+        warn!("Some devices have no samples, try a longer --duration");
+    }
+    Ok(report)
+}
+
+/// Runs the benchmark and prints the results, as JSON with `--json`
+pub async fn main(opt: &BenchmarkOpt, plugin_manager: &PluginManager) -> Result<(), Error> {
+    let report = run(opt, plugin_manager).await?;
+    info!("=================================================================================");
+    info!(" Benchmark over {:.0}s at difficulty {}", report.duration_secs, report.difficulty);
+    report.devices.iter().for_each(Measurement::log);
+    report.total.log();
+    info!(" Shares found: {}", report.shares);
+    info!("=================================================================================");
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{synthetic_job, Measurement};
+    use crate::pow::BlockSeed;
+    use crate::target::Uint256;
+
+    #[test]
+    fn test_benchmark() {
+        let measurement = Measurement::new("#0", &[900.0, 1000.0, 1100.0]);
+        assert_eq!((measurement.hashrate, measurement.stddev), (1000.0, 100.0));
+        assert_eq!((measurement.min, measurement.max, measurement.samples), (900.0, 1100.0, 3));
+        assert_eq!(Measurement::new("#0", &[]).hashrate, 0.0);
+
+        let (first, second) = (synthetic_job(1, Uint256::default()), synthetic_job(2, Uint256::default()));
+        match (first, second) {
+            (
+                BlockSeed::PartialBlock { id, header_hash, nonce_mask, .. },
+                BlockSeed::PartialBlock { header_hash: other, .. },
+            ) => {
+                assert_eq!((id.as_str(), nonce_mask), ("1", u64::MAX));
+                assert_ne!(header_hash, other);
+            }
+            _ => panic!("Expected partial blocks"),
+        }
+    }
+}
@@ -1,11 +1,26 @@
 use clap::Parser;
 use log::LevelFilter;
 
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::client::proxy::Proxy;
+use crate::client::split::SplitPool;
+use crate::client::stratum::{StratumDialect, StratumLiveness, StratumOptions};
+use crate::schedule::{self, PauseWindow};
+use crate::watchdog::WatchdogAction;
 use crate::Error;
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Clone, Debug)]
 #[clap(name = "karlsen-miner", version, about = "A Karlsen high-performance CPU/GPU miner", term_width = 0)]
 pub struct Opt {
+    #[clap(
+        long = "config",
+        help = "Read settings from this TOML file, keyed by flag name, e.g. mining-address = \"karlsen:...\". Flags on the command line take precedence. Reloaded on SIGHUP or when the file changes"
+    )]
+    pub config: Option<PathBuf>,
+
     #[clap(short, long, help = "Enable debug logging level")]
     pub debug: bool,
 
@@ -44,6 +59,147 @@ pub struct Opt {
     )]
     pub mine_when_not_synced: bool,
 
+    #[clap(
+        long = "stratum-dialect",
+        default_value = "default",
+        help = "Stratum dialect spoken by the pool. Options: (i) default (ii) ethstratum - EthereumStratum/1.0.0 (NiceHash-style)"
+    )]
+    pub stratum_dialect: StratumDialect,
+
+    #[clap(
+        long = "stratum-notify-timeout",
+        default_value = "120",
+        help = "Reconnect when the pool sends no new job for this many seconds (0 to disable)"
+    )]
+    pub stratum_notify_timeout: u64,
+
+    #[clap(
+        long = "stratum-response-timeout",
+        default_value = "30",
+        help = "Seconds to wait for the pool to answer a share or keepalive"
+    )]
+    pub stratum_response_timeout: u64,
+
+    #[clap(
+        long = "stratum-max-unanswered",
+        default_value = "3",
+        help = "Reconnect when this many shares are left unanswered past the response timeout (0 to disable)"
+    )]
+    pub stratum_max_unanswered: usize,
+
+    #[clap(
+        long = "stratum-keepalive",
+        default_value = "0",
+        help = "Send a mining.ping keepalive every this many seconds, if the pool supports it (0 to disable)"
+    )]
+    pub stratum_keepalive: u64,
+
+    #[clap(
+        long = "stratum-submit-stale",
+        help = "Keep submitting shares for jobs the pool invalidated with clean_jobs or reported as not found"
+    )]
+    pub stratum_submit_stale: bool,
+
+    #[clap(
+        long = "stratum-allow-redirect",
+        help = "Follow client.reconnect requests to another host. Without it only reconnects to the same host are followed"
+    )]
+    pub stratum_allow_redirect: bool,
+
+    #[clap(long = "stratum-record", help = "Append every stratum line sent and received to this JSONL file")]
+    pub stratum_record: Option<PathBuf>,
+
+    #[clap(
+        long = "stratum-replay",
+        help = "Replay a file recorded with --stratum-record instead of connecting to a pool, and report where the miner behaves differently"
+    )]
+    pub stratum_replay: Option<PathBuf>,
+
+    #[clap(
+        long = "proxy",
+        help = "Connect to the pool or node through a proxy: socks5://[user:pass@]host:port or http://[user:pass@]host:port. Host names are resolved by the proxy"
+    )]
+    pub proxy: Option<Proxy>,
+
+    #[clap(
+        long = "pool-split",
+        help = "Split the mining time between pools connected at the same time, given as address=percent. Repeat for every pool, the percentages must add up to 100. Replaces --karlsend-address"
+    )]
+    pub pool_split: Vec<SplitPool>,
+
+    #[clap(
+        long = "api-bind",
+        help = "Serve statistics as JSON over HTTP on this address, e.g. 127.0.0.1:4110. Endpoints: /summary, /devices, /shares, /blocks, /job and Prometheus /metrics"
+    )]
+    pub api_bind: Option<SocketAddr>,
+
+    #[clap(
+        long = "control-bind",
+        help = "Accept control requests over HTTP on this address, e.g. 127.0.0.1:4111: POST /pause, /resume, /workload, /pool and /mining-address. Anyone who can reach it controls the miner, keep it on localhost"
+    )]
+    pub control_bind: Option<SocketAddr>,
+
+    #[clap(
+        long = "tui",
+        help = "Show a full-screen dashboard instead of the log, when stdout is a terminal. Press q to go back to the log"
+    )]
+    pub tui: bool,
+
+    #[clap(
+        long = "watchdog-timeout",
+        default_value = "0",
+        help = "Act when a device hashes nothing, no new job arrives or the pool stays disconnected for this many seconds (0 to disable)"
+    )]
+    pub watchdog_timeout: u64,
+
+    #[clap(
+        long = "watchdog-device-action",
+        default_value = "restart",
+        help = "What the watchdog does about a device hashing nothing: restart, reconnect, exit or hook"
+    )]
+    pub watchdog_device_action: WatchdogAction,
+
+    #[clap(
+        long = "watchdog-job-action",
+        default_value = "reconnect",
+        help = "What the watchdog does when no new job arrives: reconnect, exit or hook"
+    )]
+    pub watchdog_job_action: WatchdogAction,
+
+    #[clap(
+        long = "watchdog-connection-action",
+        default_value = "exit",
+        help = "What the watchdog does when the pool or node stays disconnected: reconnect, exit or hook. Exit uses code 75"
+    )]
+    pub watchdog_connection_action: WatchdogAction,
+
+    #[clap(
+        long = "watchdog-hook",
+        help = "Command the hook action runs, with KARLSEN_WATCHDOG_REASON (device, job or connection) and KARLSEN_WATCHDOG_DEVICE set"
+    )]
+    pub watchdog_hook: Option<String>,
+
+    #[clap(
+        long = "pause-schedule",
+        help = "Pause mining during this weekly time range, e.g. \"mon-fri 17:00-21:00\", or \"22:00-06:00\" for every day. Repeat for more ranges"
+    )]
+    pub pause_schedule: Vec<PauseWindow>,
+
+    #[clap(
+        long = "schedule-timezone",
+        help = "Time zone of --pause-schedule, e.g. Europe/Berlin [default: the system's]"
+    )]
+    pub schedule_timezone: Option<String>,
+
+    #[clap(long = "pause-file", help = "Pause mining while this file exists")]
+    pub pause_file: Option<PathBuf>,
+
+    #[clap(
+        long = "pause-process",
+        help = "Pause mining while a process with this name runs, e.g. a game. Repeat for more processes"
+    )]
+    pub pause_process: Vec<String>,
+
     #[clap(skip)]
     pub devfund_address: String,
 }
@@ -84,6 +240,30 @@ impl Opt {
         }
         log::info!("karlsend address: {}", self.karlsend_address);
 
+        if !self.pool_split.is_empty() {
+            let total = self.pool_split.iter().map(|pool| pool.percent).sum::<u16>();
+            if self.pool_split.len() < 2 || total != 100 {
+                return Err(format!(
+                    "--pool-split needs at least two pools adding up to 100%, got {} pools adding up to {}%",
+                    self.pool_split.len(),
+                    total
+                )
+                .into());
+            }
+        }
+
+        if [self.watchdog_job_action, self.watchdog_connection_action].contains(&WatchdogAction::Restart) {
+            return Err(
+                "The watchdog can only restart devices, use reconnect, exit or hook for jobs and connections".into()
+            );
+        }
+        let actions = [self.watchdog_device_action, self.watchdog_job_action, self.watchdog_connection_action];
+        if actions.contains(&WatchdogAction::Hook) && self.watchdog_hook.is_none() {
+            return Err("The watchdog hook action needs --watchdog-hook".into());
+        }
+
+        schedule::timezone(&self.schedule_timezone)?;
+
         let miner_network = self.mining_address.split(':').next();
         self.devfund_address = "karlsen:qzrq7v5jhsc5znvtfdg6vxg7dz5x8dqe4wrh90jkdnwehp6vr8uj7csdss2l7".to_string();
         let devfund_network = self.devfund_address.split(':').next();
@@ -101,6 +281,17 @@ impl Opt {
         Ok(())
     }
 
+    /// Prefix of the addresses on the network the miner runs on
+    pub fn address_prefix(&self) -> &'static str {
+        if self.testnet {
+            "karlsentest"
+        } else if self.devnet {
+            "karlsendev"
+        } else {
+            "karlsen"
+        }
+    }
+
     fn port(&mut self) -> u16 {
         *self.port.get_or_insert({
             if self.testnet {
@@ -113,6 +304,26 @@ impl Opt {
         })
     }
 
+    pub fn stratum_options(&self) -> StratumOptions {
+        StratumOptions {
+            dialect: self.stratum_dialect,
+            liveness: self.stratum_liveness(),
+            submit_stale: self.stratum_submit_stale,
+            allow_redirect: self.stratum_allow_redirect,
+            record: self.stratum_record.clone(),
+        }
+    }
+
+    pub fn stratum_liveness(&self) -> StratumLiveness {
+        let seconds = |secs: u64| Some(secs).filter(|&secs| secs > 0).map(Duration::from_secs);
+        StratumLiveness {
+            notify_timeout: seconds(self.stratum_notify_timeout),
+            response_timeout: Duration::from_secs(self.stratum_response_timeout),
+            max_unanswered: self.stratum_max_unanswered,
+            keepalive: seconds(self.stratum_keepalive),
+        }
+    }
+
     pub fn log_level(&self) -> LevelFilter {
         if self.debug {
             LevelFilter::Debug
@@ -1,16 +1,76 @@
 use async_trait::async_trait;
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
 use tokio::sync::mpsc::Sender;
 
 pub mod grpc;
+pub mod proxy;
+pub mod split;
 pub mod stratum;
 
+use crate::miner::JobSink;
 use crate::pow::BlockSeed;
-use crate::{Error, MinerManager};
+use crate::Error;
+
+/// Server request to move the connection, possibly to another address
+#[derive(Clone, Debug)]
+pub struct Reconnect {
+    pub address: String,
+    pub wait: Duration,
+}
+
+/// Nonces already submitted for the most recent jobs, so the same solution is never sent twice
+pub struct SubmittedNonces<J> {
+    jobs: VecDeque<(J, HashSet<u64>)>,
+    capacity: usize,
+}
+
+impl<J: PartialEq> SubmittedNonces<J> {
+    pub fn new(capacity: usize) -> Self {
+        Self { jobs: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    /// Returns false if the nonce was already submitted for this job
+    pub fn insert(&mut self, job: J, nonce: u64) -> bool {
+        if let Some((_, nonces)) = self.jobs.iter_mut().rev().find(|(known, _)| *known == job) {
+            return nonces.insert(nonce);
+        }
+        if self.jobs.len() == self.capacity {
+            self.jobs.pop_front();
+        }
+        self.jobs.push_back((job, HashSet::from([nonce])));
+        true
+    }
+}
 
 #[async_trait(?Send)]
 pub trait Client {
     fn add_devfund(&mut self, address: String, percent: u16);
+    /// Whether every job advances the devfund's job counter. The pool split counts the jobs it mines itself.
+    fn count_jobs(&mut self, count: bool);
     async fn register(&mut self) -> Result<(), Error>;
-    async fn listen(&mut self, miner: &mut MinerManager) -> Result<(), Error>;
+    async fn listen(&mut self, miner: &mut dyn JobSink) -> Result<(), Error>;
     fn get_block_channel(&self) -> Sender<BlockSeed>;
+    fn reconnect_request(&self) -> Option<Reconnect> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubmittedNonces;
+
+    #[test]
+    fn test_submitted_nonces() {
+        let mut submitted = SubmittedNonces::new(2);
+        assert!(submitted.insert("1", 7));
+        assert!(!submitted.insert("1", 7));
+        assert!(submitted.insert("1", 8));
+        assert!(submitted.insert("2", 7));
+
+        // A third job evicts the oldest, whose nonces can then be sent again
+        assert!(submitted.insert("3", 7));
+        assert!(!submitted.insert("2", 7));
+        assert!(submitted.insert("1", 7));
+    }
 }
@@ -1,4 +1,5 @@
-use crate::client::Client;
+use crate::client::proxy::Proxy;
+use crate::client::{Client, SubmittedNonces};
 use crate::pow::BlockSeed;
 use crate::pow::BlockSeed::{FullBlock, PartialBlock};
 use crate::proto::karlsend_message::Payload;
@@ -6,9 +7,11 @@ use crate::proto::rpc_client::RpcClient;
 use crate::proto::{
     GetBlockTemplateRequestMessage, GetInfoRequestMessage, KarlsendMessage, NotifyNewBlockTemplateRequestMessage,
 };
-use crate::{miner::MinerManager, Error};
+use crate::stats::Statistics;
+use crate::{miner::JobSink, Error};
 use async_trait::async_trait;
-use futures_util::StreamExt;
+use futures_util::{future, StreamExt};
+use hyper_util::rt::TokioIo;
 use log::{error, info, warn};
 use rand::{rng, RngCore};
 use std::sync::atomic::{AtomicU16, Ordering};
@@ -17,9 +20,12 @@ use tokio::sync::mpsc::{self, error::SendError, Sender};
 use tokio::task::JoinHandle;
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_util::sync::{PollSendError, PollSender};
-use tonic::{transport::Channel as TonicChannel, Streaming};
+use tonic::transport::{Channel as TonicChannel, Endpoint, Uri};
+use tonic::Streaming;
+use tower::service_fn;
 
 static EXTRA_DATA: &str = concat!(env!("CARGO_PKG_VERSION"));
+const TEMPLATES_KEPT: usize = 16;
 type BlockHandle = JoinHandle<Result<(), PollSendError<KarlsendMessage>>>;
 
 #[allow(dead_code)]
@@ -32,6 +38,8 @@ pub struct KarlsendHandler {
     devfund_address: Option<String>,
     devfund_percent: u16,
     block_template_ctr: Arc<AtomicU16>,
+    count_jobs: bool,
+    statistics: Arc<Statistics>,
 
     block_channel: Sender<BlockSeed>,
     block_handle: BlockHandle,
@@ -44,12 +52,16 @@ impl Client for KarlsendHandler {
         self.devfund_percent = percent;
     }
 
+    fn count_jobs(&mut self, count: bool) {
+        self.count_jobs = count;
+    }
+
     async fn register(&mut self) -> Result<(), Error> {
         // We actually register in connect
         Ok(())
     }
 
-    async fn listen(&mut self, miner: &mut MinerManager) -> Result<(), Error> {
+    async fn listen(&mut self, miner: &mut dyn JobSink) -> Result<(), Error> {
         while let Some(msg) = self.stream.message().await? {
             match msg.payload {
                 Some(payload) => self.handle_message(payload, miner).await?,
@@ -70,16 +82,34 @@ impl KarlsendHandler {
         miner_address: String,
         mine_when_not_synced: bool,
         block_template_ctr: Option<Arc<AtomicU16>>,
+        proxy: Option<Proxy>,
+        statistics: Arc<Statistics>,
     ) -> Result<Box<Self>, Error>
     where
-        D: std::convert::TryInto<tonic::transport::Endpoint>,
+        D: std::convert::TryInto<Endpoint>,
         D::Error: Into<Error>,
     {
-        let mut client = RpcClient::connect(address).await?;
+        let endpoint = address.try_into().map_err(Into::into)?;
+        let channel = match proxy {
+            Some(proxy) => {
+                endpoint
+                    .connect_with_connector(service_fn(move |uri: Uri| {
+                        let proxy = proxy.clone();
+                        async move {
+                            let host = uri.host().ok_or("Node address is missing a host")?;
+                            let port = uri.port_u16().unwrap_or(80);
+                            proxy.connect(&format!("{}:{}", host, port)).await.map(TokioIo::new)
+                        }
+                    }))
+                    .await?
+            }
+            None => endpoint.connect().await?,
+        };
+        let mut client = RpcClient::new(channel);
         let (send_channel, recv) = mpsc::channel(2);
         send_channel.send(GetInfoRequestMessage {}.into()).await?;
         let stream = client.message_stream(ReceiverStream::new(recv)).await?.into_inner();
-        let (block_channel, block_handle) = Self::create_block_channel(send_channel.clone());
+        let (block_channel, block_handle) = Self::create_block_channel(send_channel.clone(), statistics.clone());
         Ok(Box::new(Self {
             client,
             stream,
@@ -88,23 +118,41 @@ impl KarlsendHandler {
             mine_when_not_synced,
             devfund_address: None,
             devfund_percent: 0,
+            count_jobs: true,
             block_template_ctr: block_template_ctr
                 .unwrap_or_else(|| Arc::new(AtomicU16::new((rng().next_u64() % 10_000u64) as u16))),
+            statistics,
             block_channel,
             block_handle,
         }))
     }
 
-    fn create_block_channel(send_channel: Sender<KarlsendMessage>) -> (Sender<BlockSeed>, BlockHandle) {
+    fn create_block_channel(
+        send_channel: Sender<KarlsendMessage>,
+        statistics: Arc<Statistics>,
+    ) -> (Sender<BlockSeed>, BlockHandle) {
         // KarlsendMessage::submit_block(block)
         let (send, recv) = mpsc::channel::<BlockSeed>(1);
+        let mut submitted = SubmittedNonces::new(TEMPLATES_KEPT);
         (
             send,
             tokio::spawn(async move {
                 ReceiverStream::new(recv)
-                    .map(|block_seed| match block_seed {
-                        FullBlock(block) => KarlsendMessage::submit_block(*block),
-                        PartialBlock { .. } => unreachable!("All blocks sent here should have arrived from here"),
+                    .filter_map(move |block_seed| {
+                        let block = match block_seed {
+                            FullBlock(block) => block,
+                            PartialBlock { .. } => unreachable!("All blocks sent here should have arrived from here"),
+                        };
+                        // The template is the header without its nonce
+                        let mut template = block.header.clone().expect("We checked that a header exists on creation");
+                        let nonce = std::mem::take(&mut template.nonce);
+                        if !submitted.insert(template, nonce) {
+                            statistics.block_dropped_duplicate();
+                            warn!("Dropping duplicate block (nonce: {:016x})", nonce);
+                            return future::ready(None);
+                        }
+                        statistics.block_submitted();
+                        future::ready(Some(KarlsendMessage::submit_block(*block)))
                     })
                     .map(Ok)
                     .forward(PollSender::new(send_channel))
@@ -124,11 +172,15 @@ impl KarlsendHandler {
             }
             _ => self.miner_address.clone(),
         };
-        self.block_template_ctr.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |v| Some((v + 1) % 10_000)).unwrap();
+        if self.count_jobs {
+            self.block_template_ctr
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |v| Some((v + 1) % 10_000))
+                .unwrap();
+        }
         self.client_send(GetBlockTemplateRequestMessage { pay_address, extra_data: EXTRA_DATA.into() }).await
     }
 
-    async fn handle_message(&mut self, msg: Payload, miner: &mut MinerManager) -> Result<(), Error> {
+    async fn handle_message(&mut self, msg: Payload, miner: &mut dyn JobSink) -> Result<(), Error> {
         match msg {
             Payload::BlockAddedNotification(_) => self.client_get_block_template().await?,
             Payload::NewBlockTemplateNotification(_) => self.client_get_block_template().await?,
@@ -145,7 +197,10 @@ impl KarlsendHandler {
             },
             Payload::SubmitBlockResponse(res) => match res.error {
                 None => info!("block submitted successfully!"),
-                Some(e) => warn!("Failed submitting block: {:?}", e),
+                Some(e) => {
+                    self.statistics.block_rejected();
+                    warn!("Failed submitting block: {:?}", e)
+                }
             },
             Payload::GetBlockResponse(msg) => {
                 if let Some(e) = msg.error {
@@ -178,3 +233,51 @@ impl Drop for KarlsendHandler {
         self.block_handle.abort();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{KarlsendHandler, TEMPLATES_KEPT};
+    use crate::pow::BlockSeed;
+    use crate::proto::karlsend_message::Payload;
+    use crate::proto::{RpcBlock, RpcBlockHeader};
+    use crate::stats::Statistics;
+    use std::sync::Arc;
+    use tokio::sync::mpsc;
+
+    fn block(daa_score: u64, nonce: u64) -> BlockSeed {
+        let header = RpcBlockHeader { daa_score, nonce, ..Default::default() };
+        BlockSeed::FullBlock(Box::new(RpcBlock { header: Some(header), ..Default::default() }))
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_blocks() {
+        let (send_channel, mut sent) = mpsc::channel(TEMPLATES_KEPT + 8);
+        let statistics = Arc::new(Statistics::default());
+        let (blocks, _handle) = KarlsendHandler::create_block_channel(send_channel, statistics.clone());
+        // The nonce is left out of the template, so the same solution for the same template is only sent once
+        for (daa_score, nonce) in [(1, 7), (1, 7), (1, 8), (2, 7)] {
+            blocks.send(block(daa_score, nonce)).await.unwrap();
+        }
+        // Templates that fell out of the kept ones are forgotten
+        for daa_score in 3..3 + TEMPLATES_KEPT as u64 {
+            blocks.send(block(daa_score, 0)).await.unwrap();
+        }
+        blocks.send(block(1, 7)).await.unwrap();
+        drop(blocks);
+
+        let mut nonces = Vec::new();
+        while let Some(message) = sent.recv().await {
+            match message.payload {
+                Some(Payload::SubmitBlockRequest(request)) => {
+                    let header = request.block.unwrap().header.unwrap();
+                    nonces.push((header.daa_score, header.nonce));
+                }
+                payload => panic!("Expected a block submission, got {:?}", payload),
+            }
+        }
+        let expected = [(1, 7), (1, 8), (2, 7)].into_iter().chain((3..3 + TEMPLATES_KEPT as u64).map(|daa| (daa, 0)));
+        assert_eq!(nonces, expected.chain([(1, 7)]).collect::<Vec<_>>());
+        assert_eq!(statistics.blocks().dropped_duplicate, 1);
+        assert_eq!(statistics.blocks().submitted, 4 + TEMPLATES_KEPT as u64);
+    }
+}
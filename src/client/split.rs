@@ -0,0 +1,362 @@
+//! Mining time split between several pools that stay connected at the same time.
+//! Every pool session hands its jobs to a `SessionSink`, and `PoolSplit` decides whose job the workers get.
+
+use async_trait::async_trait;
+use log::{info, warn};
+use rand::{rng, RngCore};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, Receiver, Sender, UnboundedReceiver, UnboundedSender};
+use tokio::time::MissedTickBehavior;
+
+use crate::miner::JobSink;
+use crate::pow::BlockSeed;
+use crate::Error;
+
+const SCHEDULE_RATE: Duration = Duration::from_secs(1);
+/// How far a pool may fall behind its share of the time before the workers are moved to it
+const SWITCH_MARGIN: Duration = Duration::from_secs(30);
+
+/// A pool and the percentage of the mining time it gets, given as `address=percent`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SplitPool {
+    pub address: String,
+    pub percent: u16,
+}
+
+impl FromStr for SplitPool {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("Pool split should be formatted as address=percent: {}", s);
+        let (address, percent) = s.rsplit_once('=').ok_or_else(err)?;
+        let percent = percent.trim_end_matches('%').parse::<u16>().map_err(|_| err())?;
+        if address.is_empty() || percent == 0 || percent > 100 {
+            return Err(err().into());
+        }
+        Ok(Self { address: address.to_string(), percent })
+    }
+}
+
+#[derive(Debug)]
+enum SplitEvent {
+    Connected(usize, Sender<BlockSeed>),
+    Job(usize, Option<BlockSeed>),
+    Disconnected(usize),
+}
+
+/// Stands in for the miner in a single pool session
+pub struct SessionSink {
+    index: usize,
+    events: UnboundedSender<SplitEvent>,
+    block_template_ctr: Arc<AtomicU16>,
+}
+
+impl SessionSink {
+    /// Shares found on this pool's jobs go to `block_channel` from now on
+    pub fn connected(&self, block_channel: Sender<BlockSeed>) {
+        _ = self.events.send(SplitEvent::Connected(self.index, block_channel));
+    }
+
+    pub fn disconnected(&self) {
+        _ = self.events.send(SplitEvent::Disconnected(self.index));
+    }
+
+    /// This pool's devfund job counter, advanced only for the jobs the workers get
+    pub fn block_template_ctr(&self) -> Arc<AtomicU16> {
+        self.block_template_ctr.clone()
+    }
+}
+
+#[async_trait(?Send)]
+impl JobSink for SessionSink {
+    async fn process_block(&mut self, block: Option<BlockSeed>) -> Result<(), Error> {
+        self.events.send(SplitEvent::Job(self.index, block)).map_err(|_| "Pool split scheduler is gone")?;
+        Ok(())
+    }
+}
+
+struct SplitSession {
+    address: String,
+    percent: u16,
+    job: Option<BlockSeed>,
+    /// Whether `job` was counted towards the devfund yet
+    counted: bool,
+    block_template_ctr: Arc<AtomicU16>,
+    block_channel: Option<Sender<BlockSeed>>,
+    mined: Duration,
+}
+
+/// Hands the workers the current job of one pool at a time, so every pool gets its percentage of the time.
+/// Time is balanced between the pools that currently have a job; whenever one drops out or comes back,
+/// the balance starts over instead of letting the others catch up.
+pub struct PoolSplit {
+    sessions: Vec<SplitSession>,
+    events: UnboundedReceiver<SplitEvent>,
+    active: Option<usize>,
+    /// Session whose job the workers got last, full blocks carry no job id to route them by
+    mining: Option<usize>,
+    last_tick: Instant,
+}
+
+impl PoolSplit {
+    pub fn new(pools: &[SplitPool]) -> (Self, Vec<SessionSink>) {
+        let (send, events) = mpsc::unbounded_channel();
+        let counters =
+            pools.iter().map(|_| Arc::new(AtomicU16::new((rng().next_u64() % 10_000u64) as u16))).collect::<Vec<_>>();
+        let sinks = counters
+            .iter()
+            .enumerate()
+            .map(|(index, counter)| SessionSink { index, events: send.clone(), block_template_ctr: counter.clone() })
+            .collect();
+        let sessions = pools
+            .iter()
+            .zip(counters)
+            .map(|(pool, block_template_ctr)| SplitSession {
+                address: pool.address.clone(),
+                percent: pool.percent,
+                job: None,
+                counted: false,
+                block_template_ctr,
+                block_channel: None,
+                mined: Duration::ZERO,
+            })
+            .collect();
+        (Self { sessions, events, active: None, mining: None, last_tick: Instant::now() }, sinks)
+    }
+
+    /// Routes jobs from the sessions to `miner`, and the shares it finds back to their pool
+    pub async fn run(mut self, miner: &mut dyn JobSink, shares: &mut Receiver<BlockSeed>) -> Result<(), Error> {
+        let mut ticker = tokio::time::interval(SCHEDULE_RATE);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                event = self.events.recv() => match event {
+                    Some(event) => self.handle(event, miner).await?,
+                    None => return Err("All pool sessions ended".into()),
+                },
+                share = shares.recv() => match share {
+                    Some(share) => self.route_share(share).await,
+                    None => return Err("Miner closed the share channel".into()),
+                },
+                _ = ticker.tick() => self.schedule(miner, false).await?,
+            }
+        }
+    }
+
+    async fn handle(&mut self, event: SplitEvent, miner: &mut dyn JobSink) -> Result<(), Error> {
+        let eligible = self.eligible();
+        match event {
+            SplitEvent::Connected(index, block_channel) => self.sessions[index].block_channel = Some(block_channel),
+            SplitEvent::Job(index, job) => {
+                self.sessions[index].job = job;
+                self.sessions[index].counted = false;
+                if self.active == Some(index) && self.eligible() == eligible {
+                    return self.forward(index, miner).await;
+                }
+            }
+            SplitEvent::Disconnected(index) => {
+                self.sessions[index].job = None;
+                self.sessions[index].block_channel = None;
+            }
+        }
+        self.schedule(miner, self.eligible() != eligible).await
+    }
+
+    /// Moves the workers to another pool when the current one got too far ahead of its share
+    async fn schedule(&mut self, miner: &mut dyn JobSink, reset: bool) -> Result<(), Error> {
+        let elapsed = self.last_tick.elapsed();
+        self.last_tick = Instant::now();
+        if let Some(active) = self.active {
+            self.sessions[active].mined += elapsed;
+        }
+        if reset {
+            self.sessions.iter_mut().for_each(|session| session.mined = Duration::ZERO);
+        }
+        let next = self.pick();
+        if next == self.active {
+            return Ok(());
+        }
+        self.active = next;
+        match next {
+            Some(index) => {
+                let session = &self.sessions[index];
+                info!("Mining on {} ({}% of the time)", session.address, session.percent);
+                self.forward(index, miner).await
+            }
+            None => {
+                warn!("None of the split pools has a job");
+                miner.process_block(None).await
+            }
+        }
+    }
+
+    fn eligible(&self) -> Vec<usize> {
+        (0..self.sessions.len()).filter(|&index| self.sessions[index].job.is_some()).collect()
+    }
+
+    /// The pool furthest behind its share of the time, unless the active one is still close enough
+    fn pick(&self) -> Option<usize> {
+        let eligible = self.eligible();
+        let total = eligible.iter().map(|&index| self.sessions[index].mined).sum::<Duration>().as_secs_f64();
+        let percent = eligible.iter().map(|&index| self.sessions[index].percent as f64).sum::<f64>();
+        let deficit = |index: usize| {
+            let session = &self.sessions[index];
+            total * session.percent as f64 / percent - session.mined.as_secs_f64()
+        };
+        let behind = eligible.iter().copied().max_by(|&a, &b| deficit(a).total_cmp(&deficit(b)).then(b.cmp(&a)))?;
+        match self.active {
+            Some(active)
+                if eligible.contains(&active) && deficit(behind) - deficit(active) < SWITCH_MARGIN.as_secs_f64() =>
+            {
+                Some(active)
+            }
+            _ => Some(behind),
+        }
+    }
+
+    async fn forward(&mut self, index: usize, miner: &mut dyn JobSink) -> Result<(), Error> {
+        self.mining = Some(index);
+        let session = &mut self.sessions[index];
+        if session.job.is_some() && !session.counted {
+            session.counted = true;
+            session
+                .block_template_ctr
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |v| Some((v + 1) % 10_000))
+                .unwrap();
+        }
+        miner.process_block(self.sessions[index].job.clone().map(|job| tag(index, job))).await
+    }
+
+    async fn route_share(&mut self, share: BlockSeed) {
+        let (index, share) = match untag(share) {
+            (Some(index), share) => (index, share),
+            (None, share) => match self.mining {
+                Some(index) => (index, share),
+                None => return,
+            },
+        };
+        match self.sessions.get(index).and_then(|session| session.block_channel.as_ref()) {
+            Some(block_channel) => {
+                if block_channel.send(share).await.is_err() {
+                    warn!("Dropping share, {} is reconnecting", self.sessions[index].address);
+                }
+            }
+            None => warn!("Dropping share for a pool that is not connected"),
+        }
+    }
+}
+
+/// Prefixes the job id with the session, so the shares found on it can be routed back
+fn tag(index: usize, mut job: BlockSeed) -> BlockSeed {
+    if let BlockSeed::PartialBlock { ref mut id, .. } = job {
+        *id = format!("{}/{}", index, id);
+    }
+    job
+}
+
+fn untag(mut share: BlockSeed) -> (Option<usize>, BlockSeed) {
+    let mut index = None;
+    if let BlockSeed::PartialBlock { ref mut id, .. } = share {
+        if let Some((prefix, job_id)) = id.split_once('/') {
+            index = prefix.parse().ok();
+            *id = job_id.to_string();
+        }
+    }
+    (index, share)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PoolSplit, SplitPool};
+    use crate::miner::JobSink;
+    use crate::pow::BlockSeed;
+    use crate::Error;
+    use async_trait::async_trait;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    #[derive(Default)]
+    struct Jobs(Vec<Option<String>>);
+
+    #[async_trait(?Send)]
+    impl JobSink for Jobs {
+        async fn process_block(&mut self, block: Option<BlockSeed>) -> Result<(), Error> {
+            self.0.push(block.map(|block| match block {
+                BlockSeed::PartialBlock { id, .. } => id,
+                BlockSeed::FullBlock(_) => unreachable!(),
+            }));
+            Ok(())
+        }
+    }
+
+    fn job(id: &str) -> Option<BlockSeed> {
+        Some(BlockSeed::PartialBlock {
+            id: id.into(),
+            header_hash: [0; 4],
+            timestamp: 0,
+            nonce: 0,
+            target: Default::default(),
+            nonce_mask: u64::MAX,
+            nonce_fixed: 0,
+            hash: None,
+        })
+    }
+
+    #[test]
+    fn test_parse_split_pool() {
+        let pool: SplitPool = "stratum+tcp://pool-a:5555=60".parse().unwrap();
+        assert_eq!(pool, SplitPool { address: "stratum+tcp://pool-a:5555".into(), percent: 60 });
+        assert_eq!("stratum+tcp://pool-b:5555=40%".parse::<SplitPool>().unwrap().percent, 40);
+        assert!("stratum+tcp://pool-a:5555".parse::<SplitPool>().is_err());
+        assert!("stratum+tcp://pool-a:5555=0".parse::<SplitPool>().is_err());
+        assert!("stratum+tcp://pool-a:5555=101".parse::<SplitPool>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pool_split() {
+        let pools = ["a=60".parse().unwrap(), "b=40".parse().unwrap()];
+        let (mut split, mut sinks) = PoolSplit::new(&pools);
+        let mut miner = Jobs::default();
+        let (share_send, mut share_recv) = mpsc::channel(1);
+        let counters = sinks.iter().map(|sink| sink.block_template_ctr()).collect::<Vec<_>>();
+        counters.iter().for_each(|counter| counter.store(0, Ordering::SeqCst));
+        let counts = || counters.iter().map(|counter| counter.load(Ordering::SeqCst)).collect::<Vec<_>>();
+        sinks[1].connected(share_send);
+        sinks[0].process_block(job("1")).await.unwrap();
+        sinks[1].process_block(job("7")).await.unwrap();
+        while let Ok(event) = split.events.try_recv() {
+            split.handle(event, &mut miner).await.unwrap();
+        }
+        // Both pools have a job, the first one starts
+        assert_eq!(split.active, Some(0));
+        assert_eq!(miner.0.last(), Some(&Some("0/1".to_string())));
+        // Only the job the workers got counts towards the devfund
+        assert_eq!(counts(), [1, 0]);
+
+        // Stays within the margin, then moves to the pool behind its share
+        split.sessions[0].mined = Duration::from_secs(30);
+        assert_eq!(split.pick(), Some(0));
+        split.sessions[0].mined = Duration::from_secs(40);
+        assert_eq!(split.pick(), Some(1));
+        split.schedule(&mut miner, false).await.unwrap();
+        assert_eq!(miner.0.last(), Some(&Some("1/7".to_string())));
+        assert_eq!(counts(), [1, 1]);
+        sinks[0].process_block(job("2")).await.unwrap();
+        let event = split.events.try_recv().unwrap();
+        split.handle(event, &mut miner).await.unwrap();
+        assert_eq!(miner.0.last(), Some(&Some("1/7".to_string())));
+        assert_eq!(counts(), [1, 1]);
+
+        // Shares go back to the pool that sent the job, with its own job id
+        split.route_share(job("1/7").unwrap()).await;
+        drop(split);
+        match share_recv.recv().await {
+            Some(BlockSeed::PartialBlock { id, .. }) => assert_eq!(id, "7"),
+            _ => panic!("Share was not routed"),
+        }
+    }
+}
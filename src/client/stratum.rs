@@ -1,33 +1,41 @@
 use futures::prelude::*;
 use native_tls::TlsConnector as NativeTlsConnector;
-use std::collections::HashMap;
-use std::fmt::{Display, Formatter};
+use socket2::{SockRef, TcpKeepalive};
+use std::collections::VecDeque;
+use std::path::PathBuf;
 use std::pin::Pin;
-use std::sync::atomic::{AtomicU16, AtomicU32, AtomicU64, Ordering};
-use std::sync::Arc;
-use std::time::Duration;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU16, AtomicU32, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
 use tokio::net::TcpStream;
 use tokio_native_tls::TlsConnector;
 use tokio_util::codec::Framed;
 
+#[cfg(test)]
+mod mock_pool;
+pub mod recording;
 mod statum_codec;
 
+use crate::client::proxy::Proxy;
+use crate::client::stratum::recording::{RecordedLine, ReplayReport, StratumRecorder};
 use crate::client::stratum::statum_codec::{ErrorCode, MiningNotify, MiningSubmit, NewLineJsonCodecError, StratumLine};
 use crate::client::stratum::statum_codec::{
     MiningSubscribe, SetExtranonce, StratumCommand, StratumError, StratumLinePayload, StratumResult,
 };
-use crate::client::Client;
+use crate::client::{Client, Reconnect, SubmittedNonces};
 use crate::pow::BlockSeed;
 use crate::pow::BlockSeed::PartialBlock;
-use crate::{miner::MinerManager, Error, Uint256};
+use crate::stats::{SessionStats, ShareEvent, Statistics};
+use crate::{miner::JobSink, Error, Uint256};
 use async_trait::async_trait;
 use futures_util::TryStreamExt;
-use log::{error, info, warn};
-use num::Float;
+use log::{debug, error, info, warn};
+use num::{BigUint, Float, ToPrimitive};
 use rand::{rng, RngCore};
+use serde_json::Value;
 use statum_codec::NewLineJsonCodec;
 use tokio::sync::mpsc::{self, Sender};
-use tokio::sync::Mutex;
 use tokio::task;
 use tokio::task::JoinHandle;
 use tokio::time::MissedTickBehavior;
@@ -37,43 +45,91 @@ use tokio_util::sync::{PollSendError, PollSender};
 //const DIFFICULTY_1_TARGET: Uint256 = Uint256([0x00000000ffff0000, 0x0000000000000000, 0x0000000000000000, 0x0000000000000000]);
 const DIFFICULTY_1_TARGET: (u64, i16) = (0xffffu64, 208); // 0xffff 2^208
 const LOG_RATE: Duration = Duration::from_secs(30);
+const ETH_STRATUM_VERSION: &str = "EthereumStratum/1.0.0";
+const JOB_HISTORY: usize = 16;
+const LIVENESS_CHECK_RATE: Duration = Duration::from_secs(1);
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
 
 type BlockHandle = JoinHandle<Result<(), PollSendError<StratumLine>>>;
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StratumDialect {
+    Default,
+    EthStratum,
+}
+
+impl FromStr for StratumDialect {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "default" => Ok(Self::Default),
+            "ethstratum" => Ok(Self::EthStratum),
+            _ => Err("Unknown stratum dialect".into()),
+        }
+    }
+}
+
+/// Thresholds after which a silent pool connection is considered dead and gets reconnected
+#[derive(Copy, Clone, Debug)]
+pub struct StratumLiveness {
+    pub notify_timeout: Option<Duration>,
+    pub response_timeout: Duration,
+    pub max_unanswered: usize,
+    pub keepalive: Option<Duration>,
+}
+
+/// Pool connection settings that are not part of the address
+#[derive(Clone, Debug)]
+pub struct StratumOptions {
+    pub dialect: StratumDialect,
+    pub liveness: StratumLiveness,
+    pub submit_stale: bool,
+    /// Follow `client.reconnect` to another host, which then gets the payout address
+    pub allow_redirect: bool,
+    pub record: Option<PathBuf>,
+}
+
+/// Target a job was notified with. Difficulty changes only apply to jobs notified after them.
+#[derive(Clone, Debug)]
+struct Job {
+    id: String,
+    difficulty: f64,
+    target: Uint256,
+    valid: bool,
+}
+
+/// Recently notified jobs, so shares found during a job switch can be matched with their job
 #[derive(Default)]
-pub struct ShareStats {
-    pub accepted: AtomicU64,
-    pub stale: AtomicU64,
-    pub low_diff: AtomicU64,
-    pub duplicate: AtomicU64,
-    pub shares_pending: Mutex<HashMap<u32, String>>,
+struct JobHistory {
+    jobs: VecDeque<Job>,
+    /// Whether the pool has sent clean_jobs, after which jobs older than the history are stale
+    cleaned: bool,
 }
 
-static mut SHARE_STATS: Option<Arc<ShareStats>> = None;
+impl JobHistory {
+    fn push(&mut self, job: Job, clean_jobs: bool) {
+        if clean_jobs {
+            self.jobs.iter_mut().for_each(|job| job.valid = false);
+            self.cleaned = true;
+        }
+        if self.jobs.len() == JOB_HISTORY {
+            self.jobs.pop_front();
+        }
+        self.jobs.push_back(job);
+    }
 
-impl Display for ShareStats {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Shares: {}{}{}{}Pending: {}",
-            match self.accepted.load(Ordering::SeqCst) {
-                0 => "".to_string(),
-                v => format!("Accepted: {} ", v),
-            },
-            match self.stale.load(Ordering::SeqCst) {
-                0 => "".to_string(),
-                v => format!("Stale: {} ", v),
-            },
-            match self.low_diff.load(Ordering::SeqCst) {
-                0 => "".to_string(),
-                v => format!("Low difficulty: {} ", v),
-            },
-            match self.duplicate.load(Ordering::SeqCst) {
-                0 => "".to_string(),
-                v => format!("Duplicate: {} ", v),
-            },
-            self.shares_pending.try_lock().unwrap().len()
-        )
+    fn get(&self, id: &str) -> Option<&Job> {
+        self.jobs.iter().rev().find(|job| job.id == id)
+    }
+
+    fn invalidate(&mut self, id: &str) {
+        self.jobs.iter_mut().filter(|job| job.id == id).for_each(|job| job.valid = false);
+    }
+
+    /// Jobs that fell out of the history are only given the benefit of the doubt until the first clean_jobs
+    fn is_valid(&self, id: &str) -> bool {
+        self.get(id).map_or(!self.cleaned, |job| job.valid)
     }
 }
 
@@ -82,6 +138,10 @@ pub struct StratumHandler {
     log_handler: JoinHandle<()>,
 
     //client: Framed<TcpStream, NewLineJsonCodec>,
+    address: String,
+    use_ssl: bool,
+    reconnect: Option<Reconnect>,
+    allow_redirect: bool,
     send_channel: Sender<StratumLine>,
     stream: Pin<Box<dyn Stream<Item = Result<StratumLine, NewLineJsonCodecError>>>>,
     miner_address: String,
@@ -90,15 +150,26 @@ pub struct StratumHandler {
     devfund_percent: u16,
     mining_dev: Option<bool>,
     block_template_ctr: Arc<AtomicU16>,
+    count_jobs: bool,
+    dialect: StratumDialect,
+    extranonce_subscribe_id: Option<u32>,
+
+    liveness: StratumLiveness,
+    last_notify: Instant,
+    ping_id: Option<u32>,
+    ping_sent: Instant,
+    unanswered_shares: usize,
 
+    difficulty_pool: f64,
     target_pool: Uint256,
     target_real: Uint256,
+    jobs: Arc<std::sync::Mutex<JobHistory>>,
     nonce_mask: u64,
     nonce_fixed: u64,
     extranonce: Option<String>,
     last_stratum_id: Arc<AtomicU32>,
 
-    shares_stats: Arc<ShareStats>,
+    shares_stats: Arc<SessionStats>,
     block_channel: Sender<BlockSeed>,
     block_handle: BlockHandle,
 }
@@ -110,17 +181,24 @@ impl Client for StratumHandler {
         self.devfund_percent = percent;
     }
 
+    fn count_jobs(&mut self, count: bool) {
+        self.count_jobs = count;
+    }
+
     async fn register(&mut self) -> Result<(), Error> {
         let mut id = { Some(self.last_stratum_id.fetch_add(1, Ordering::SeqCst)) };
+        let agent = format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        let subscribe = match self.dialect {
+            StratumDialect::Default => MiningSubscribe::MiningSubscribeDefault((
+                agent,
+                //self.extranonce.clone().unwrap_or("0xffffffff".into())
+            )),
+            StratumDialect::EthStratum => MiningSubscribe::MiningSubscribeOptions((agent, ETH_STRATUM_VERSION.into())),
+        };
         self.send_channel
             .send(StratumLine {
                 id,
-                payload: StratumLinePayload::StratumCommand(StratumCommand::Subscribe(
-                    MiningSubscribe::MiningSubscribeDefault((
-                        format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
-                        //self.extranonce.clone().unwrap_or("0xffffffff".into())
-                    )),
-                )),
+                payload: StratumLinePayload::StratumCommand(StratumCommand::Subscribe(subscribe)),
                 jsonrpc: None,
                 error: None,
             })
@@ -149,11 +227,26 @@ impl Client for StratumHandler {
                 error: None,
             })
             .await?;
+
+        if self.dialect == StratumDialect::EthStratum {
+            id = Some(self.last_stratum_id.fetch_add(1, Ordering::SeqCst));
+            self.extranonce_subscribe_id = id;
+            self.send_channel
+                .send(StratumLine {
+                    id,
+                    payload: StratumLinePayload::StratumCommand(StratumCommand::ExtranonceSubscribe(vec![])),
+                    jsonrpc: None,
+                    error: None,
+                })
+                .await?;
+        }
         Ok(())
     }
 
-    async fn listen(&mut self, miner: &mut MinerManager) -> Result<(), Error> {
+    async fn listen(&mut self, miner: &mut dyn JobSink) -> Result<(), Error> {
         info!("Waiting for stuff");
+        let mut liveness_ticker = tokio::time::interval(LIVENESS_CHECK_RATE);
+        liveness_ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
         loop {
             {
                 if self.devfund_percent > 0 {
@@ -167,9 +260,15 @@ impl Client for StratumHandler {
                     }
                 }
             }
-            match self.stream.try_next().await? {
-                Some(msg) => self.handle_message(msg, miner).await?,
-                None => return Err("stratum message payload is empty".into()),
+            tokio::select! {
+                msg = self.stream.try_next() => match msg? {
+                    Some(msg) => self.handle_message(msg, miner).await?,
+                    None => return Err("stratum message payload is empty".into()),
+                },
+                _ = liveness_ticker.tick() => self.check_liveness().await?,
+            }
+            if self.reconnect.is_some() {
+                return Ok(());
             }
         }
     }
@@ -177,20 +276,35 @@ impl Client for StratumHandler {
     fn get_block_channel(&self) -> Sender<BlockSeed> {
         self.block_channel.clone()
     }
+
+    fn reconnect_request(&self) -> Option<Reconnect> {
+        self.reconnect.clone()
+    }
 }
 
 impl StratumHandler {
+    #[allow(clippy::too_many_arguments)]
     pub async fn connect(
         address: String,
         miner_address: String,
         mine_when_not_synced: bool,
         block_template_ctr: Option<Arc<AtomicU16>>,
         use_ssl: bool,
+        options: StratumOptions,
+        proxy: Option<Proxy>,
+        statistics: &Statistics,
     ) -> Result<Box<Self>, Error> {
         info!("Connecting to {}", address);
-        let socket = TcpStream::connect(&address).await?;
+        let socket = match proxy {
+            Some(ref proxy) => proxy.connect(&address).await?,
+            None => TcpStream::connect(&address).await?,
+        };
+        if let Err(e) = SockRef::from(&socket).set_tcp_keepalive(&TcpKeepalive::new().with_time(TCP_KEEPALIVE)) {
+            warn!("Failed enabling TCP keepalive: {}", e);
+        }
 
         let (send_channel, recv) = mpsc::channel::<StratumLine>(3);
+        let recorder = options.record.as_deref().map(StratumRecorder::create).transpose()?;
 
         let stream: Pin<Box<dyn Stream<Item = Result<StratumLine, NewLineJsonCodecError>>>> = if use_ssl {
             info!("Using SSL connection");
@@ -200,46 +314,105 @@ impl StratumHandler {
                     .danger_accept_invalid_hostnames(true)
                     .build()?,
             );
-            let tls_stream = connector.connect("", socket).await?;
-            let client = Framed::new(tls_stream, NewLineJsonCodec::new());
+            let tls_stream = connector.connect(server_name(&address), socket).await?;
+            let client = Framed::new(tls_stream, NewLineJsonCodec::with_recorder(recorder));
             let (sink, stream) = client.split();
             tokio::spawn(async move { ReceiverStream::new(recv).map(Ok).forward(sink).await });
             Box::pin(stream)
         } else {
             info!("Using TCP connection");
-            let client = Framed::new(socket, NewLineJsonCodec::new());
+            let client = Framed::new(socket, NewLineJsonCodec::with_recorder(recorder));
             let (sink, stream) = client.split();
             tokio::spawn(async move { ReceiverStream::new(recv).map(Ok).forward(sink).await });
             Box::pin(stream)
         };
 
-        let share_state = unsafe {
-            #[allow(static_mut_refs)]
-            if SHARE_STATS.is_none() {
-                SHARE_STATS = Some(Arc::new(ShareStats::default()));
-            }
-            #[allow(static_mut_refs)]
-            SHARE_STATS.clone().unwrap()
-        };
+        let share_state = statistics.start_session(&address);
+        Ok(Self::new(
+            address,
+            use_ssl,
+            send_channel,
+            stream,
+            miner_address,
+            mine_when_not_synced,
+            block_template_ctr,
+            options,
+            share_state,
+        ))
+    }
+
+    /// Plays the pool side of a recording made with `--stratum-record` against a handler without a pool
+    pub fn replay(
+        recording: Vec<RecordedLine>,
+        miner_address: String,
+        options: StratumOptions,
+        statistics: &Statistics,
+    ) -> (Box<Self>, JoinHandle<ReplayReport>) {
+        let (send_channel, sent) = mpsc::channel::<StratumLine>(3);
+        let (pool, recv) = mpsc::channel(1);
+        let handler = Self::new(
+            "replay".into(),
+            false,
+            send_channel,
+            Box::pin(ReceiverStream::new(recv)),
+            miner_address,
+            false,
+            None,
+            options,
+            statistics.start_session("replay"),
+        );
+        let report = tokio::spawn(recording::replay(recording, pool, sent, handler.block_channel.clone()));
+        (handler, report)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        address: String,
+        use_ssl: bool,
+        send_channel: Sender<StratumLine>,
+        stream: Pin<Box<dyn Stream<Item = Result<StratumLine, NewLineJsonCodecError>>>>,
+        miner_address: String,
+        mine_when_not_synced: bool,
+        block_template_ctr: Option<Arc<AtomicU16>>,
+        options: StratumOptions,
+        share_state: Arc<SessionStats>,
+    ) -> Box<Self> {
         let last_stratum_id = Arc::new(AtomicU32::new(0));
+        let jobs = Arc::new(std::sync::Mutex::new(JobHistory::default()));
         let (block_channel, block_handle) = Self::create_block_channel(
             send_channel.clone(),
             miner_address.clone(),
             last_stratum_id.clone(),
             share_state.clone(),
+            jobs.clone(),
+            options.clone(),
         );
-        Ok(Box::new(Self {
-            log_handler: task::spawn(Self::log_shares(share_state.clone())),
+        Box::new(Self {
+            log_handler: task::spawn(Self::log_shares(Arc::downgrade(&share_state))),
+            address,
+            use_ssl,
+            reconnect: None,
+            allow_redirect: options.allow_redirect,
             stream,
             send_channel,
             miner_address,
             mine_when_not_synced,
             devfund_address: None,
             devfund_percent: 0,
+            count_jobs: true,
             block_template_ctr: block_template_ctr
                 .unwrap_or_else(|| Arc::new(AtomicU16::new((rng().next_u64() % 10_000u64) as u16))),
+            dialect: options.dialect,
+            extranonce_subscribe_id: None,
+            liveness: options.liveness,
+            last_notify: Instant::now(),
+            ping_id: None,
+            ping_sent: Instant::now(),
+            unanswered_shares: 0,
+            difficulty_pool: 0.0,
             target_pool: Default::default(),
             target_real: Default::default(),
+            jobs,
             nonce_mask: 0,
             nonce_fixed: 0,
             extranonce: None,
@@ -248,44 +421,54 @@ impl StratumHandler {
             mining_dev: None,
             block_channel,
             block_handle,
-        }))
+        })
     }
 
     fn create_block_channel(
         send_channel: Sender<StratumLine>,
         miner_address: String,
         last_stratum_id: Arc<AtomicU32>,
-        share_stats: Arc<ShareStats>,
+        share_stats: Arc<SessionStats>,
+        jobs: Arc<std::sync::Mutex<JobHistory>>,
+        options: StratumOptions,
     ) -> (Sender<BlockSeed>, BlockHandle) {
         let (send, recv) = mpsc::channel::<BlockSeed>(1);
+        let mut submitted = SubmittedNonces::new(JOB_HISTORY);
 
         let handle = tokio::spawn(async move {
             ReceiverStream::new(recv)
-                .map(move |block_seed| {
-                    let (nonce, id) = match block_seed {
-                        BlockSeed::PartialBlock { ref nonce, ref id, .. } => (nonce, id),
+                .filter_map(move |block_seed| {
+                    let (nonce, id, nonce_mask) = match block_seed {
+                        BlockSeed::PartialBlock { ref nonce, ref id, ref nonce_mask, .. } => (nonce, id, nonce_mask),
                         BlockSeed::FullBlock(_) => unreachable!(),
                     };
-                    let msg_id = last_stratum_id.fetch_add(1, Ordering::SeqCst);
-                    {
-                        share_stats.shares_pending.try_lock().unwrap().insert(
-                            msg_id,
-                            //SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
-                            id.clone(), //block_seed.clone()
-                        );
+                    if !options.submit_stale && !jobs.lock().unwrap().is_valid(id) {
+                        share_stats.record(ShareEvent::Suppressed);
+                        warn!("Dropping share for invalidated job {}", id);
+                        return future::ready(None);
+                    }
+                    if !submitted.insert(id.clone(), *nonce) {
+                        share_stats.record(ShareEvent::DroppedDuplicate);
+                        warn!("Dropping duplicate share (Job id: {}, nonce: {:016x})", id, nonce);
+                        return future::ready(None);
                     }
-                    StratumLine {
+                    // EthereumStratum/1.0 expects only the miner part of the nonce, without the extranonce
+                    let nonce = match options.dialect {
+                        StratumDialect::Default => format!("{:016x}", nonce),
+                        StratumDialect::EthStratum => {
+                            format!("{:0width$x}", nonce & nonce_mask, width = nonce_mask.count_ones() as usize / 4)
+                        }
+                    };
+                    let msg_id = last_stratum_id.fetch_add(1, Ordering::SeqCst);
+                    share_stats.submitted(msg_id, id.clone());
+                    future::ready(Some(StratumLine {
                         id: Some(msg_id),
                         payload: StratumLinePayload::StratumCommand(StratumCommand::MiningSubmit(
-                            MiningSubmit::MiningSubmitShort((
-                                miner_address.clone(),
-                                id.into(),
-                                format!("{:016x}", nonce),
-                            )),
+                            MiningSubmit::MiningSubmitShort((miner_address.clone(), id.into(), nonce)),
                         )),
                         jsonrpc: None,
                         error: None,
-                    }
+                    }))
                 })
                 .map(Ok)
                 .forward(PollSender::new(send_channel))
@@ -294,22 +477,33 @@ impl StratumHandler {
         (send, handle)
     }
 
-    async fn handle_message(&mut self, msg: StratumLine, miner: &mut MinerManager) -> Result<(), Error> {
+    async fn handle_message(&mut self, msg: StratumLine, miner: &mut dyn JobSink) -> Result<(), Error> {
+        if msg.id.is_some() && msg.id == self.ping_id {
+            // Any answer, even an error, shows the pool is alive
+            self.ping_id = None;
+            return Ok(());
+        }
         match msg.clone() {
             StratumLine { id, payload, error: None, .. } => {
                 match payload {
                     StratumLinePayload::StratumResult { result } if id.is_some() => {
                         match result {
                             StratumResult::Plain(Some(true)) | StratumResult::Eth((true, _)) => {
-                                if let Some(_jobid) = self
-                                    .shares_stats
-                                    .shares_pending
-                                    .try_lock()
-                                    .unwrap()
-                                    .remove(&id.expect("We checked id is not none"))
-                                {
-                                    self.shares_stats.accepted.fetch_add(1, Ordering::SeqCst);
+                                let answered = self.shares_stats.answer(id.expect("We checked id is not none"));
+                                if let Some(jobid) = answered {
+                                    self.unanswered_shares = 0;
                                     info!("Share accepted");
+                                    let target = match self.jobs.lock().unwrap().get(&jobid) {
+                                        Some(job) => {
+                                            debug!(
+                                                "Accepted share for job {} at difficulty {}",
+                                                job.id, job.difficulty
+                                            );
+                                            job.target
+                                        }
+                                        None => self.target_pool,
+                                    };
+                                    self.shares_stats.accepted(target_to_work(target));
                                 } else {
                                     info!("{:?} (Last: {})", msg.clone(), self.last_stratum_id.load(Ordering::SeqCst));
                                     warn!("Ignoring result for now");
@@ -326,6 +520,13 @@ impl StratumHandler {
                                 }
                                 Ok(())*/
                             }
+                            StratumResult::SubscribeEth((ref _subscription, ref extranonce)) => {
+                                self.set_extranonce_eth(extranonce.as_str())
+                            }
+                            StratumResult::Plain(Some(false)) if id == self.extranonce_subscribe_id => {
+                                warn!("Pool does not support mining.extranonce.subscribe");
+                                Ok(())
+                            }
                             _ => Err(format!("Inconsistent stratum message: {:?}", msg).into()),
                         }
                     }
@@ -334,26 +535,63 @@ impl StratumHandler {
                             ref extranonce,
                             ref nonce_size,
                         ))) => self.set_extranonce(extranonce.as_str(), nonce_size),
+                        StratumCommand::SetExtranonce(SetExtranonce::SetExtranoncePlainEth((ref extranonce,))) => {
+                            self.set_extranonce_eth(extranonce.as_str())
+                        }
                         StratumCommand::MiningSetDifficulty((ref difficulty,)) => self.set_difficulty(difficulty),
+                        StratumCommand::MiningSetTarget((ref target,)) => self.set_target(target.as_str()),
                         StratumCommand::MiningNotify(MiningNotify::MiningNotifyShort((id, header_hash, timestamp))) => {
-                            self.block_template_ctr
-                                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |v| Some((v + 1) % 10_000))
-                                .unwrap();
-                            miner
-                                .process_block(Some(PartialBlock {
+                            self.notify(id, header_hash, timestamp, false, miner).await
+                        }
+                        StratumCommand::MiningNotify(MiningNotify::MiningNotifyEth((id, ref header, clean_jobs))) => {
+                            let (header_hash, timestamp) = Self::parse_header(header)?;
+                            self.notify(id, header_hash, timestamp, clean_jobs, miner).await
+                        }
+                        StratumCommand::ClientReconnect(ref params) => self.request_reconnect(params),
+                        StratumCommand::ClientShowMessage((ref message,)) => {
+                            warn!("Message from pool: {}", message);
+                            Ok(())
+                        }
+                        StratumCommand::ClientGetVersion(_) => {
+                            self.send_channel
+                                .send(StratumLine {
                                     id,
-                                    header_hash,
-                                    timestamp,
-                                    nonce: 0,
-                                    target: self.target_pool,
-                                    nonce_mask: self.nonce_mask,
-                                    nonce_fixed: self.nonce_fixed,
-                                    hash: None,
-                                }))
-                                .await
+                                    payload: StratumLinePayload::StratumResult {
+                                        result: StratumResult::Text(format!(
+                                            "{}/{}",
+                                            env!("CARGO_PKG_NAME"),
+                                            env!("CARGO_PKG_VERSION")
+                                        )),
+                                    },
+                                    jsonrpc: None,
+                                    error: None,
+                                })
+                                .await?;
+                            Ok(())
+                        }
+                        _ => {
+                            warn!("Ignoring unexpected stratum message: {:?}", msg);
+                            Ok(())
                         }
-                        _ => Err(format!("Unexpected stratum message: {:?}", msg).into()),
                     },
+                    StratumLinePayload::Unknown { ref method, .. } => {
+                        warn!("Ignoring unknown stratum method {}: {:?}", method, msg);
+                        if id.is_some() {
+                            self.send_channel
+                                .send(StratumLine {
+                                    id,
+                                    payload: StratumLinePayload::StratumResult { result: StratumResult::Plain(None) },
+                                    jsonrpc: None,
+                                    error: Some(StratumError {
+                                        code: ErrorCode::Unknown,
+                                        message: format!("Method not supported: {}", method),
+                                        data: None,
+                                    }),
+                                })
+                                .await?;
+                        }
+                        Ok(())
+                    }
                     _ => Err(format!("Inconsistent stratum message: {:?}", msg).into()),
                 }
             }
@@ -363,25 +601,39 @@ impl StratumHandler {
                 error: Some(StratumError { code, message: error, .. }),
                 ..
             } => {
-                let jobid = { self.shares_stats.shares_pending.try_lock().unwrap().remove(&id) }.unwrap();
+                if Some(id) == self.extranonce_subscribe_id {
+                    warn!("Pool does not support mining.extranonce.subscribe: {}", error);
+                    return Ok(());
+                }
+                let jobid = self.shares_stats.answer(id).unwrap_or_else(|| "unknown".into());
+                self.unanswered_shares = 0;
                 match code {
                     ErrorCode::Unknown => {
                         error!("Got error code {}: {}", code, error);
                         Err(error.into())
                     }
                     ErrorCode::JobNotFound => {
-                        self.shares_stats.stale.fetch_add(1, Ordering::SeqCst);
+                        self.shares_stats.record(ShareEvent::Stale);
+                        self.jobs.lock().unwrap().invalidate(&jobid);
                         warn!("Stale share (Job id: {:?})", jobid);
                         Ok(())
                     }
                     ErrorCode::DuplicateShare => {
-                        self.shares_stats.duplicate.fetch_add(1, Ordering::SeqCst);
+                        self.shares_stats.record(ShareEvent::Duplicate);
                         warn!("Duplicate share (Job id: {:?})", jobid);
                         Ok(())
                     }
                     ErrorCode::LowDifficultyShare => {
-                        self.shares_stats.low_diff.fetch_add(1, Ordering::SeqCst);
-                        warn!("Low difficulty share (Job id: {:?})", jobid);
+                        self.shares_stats.record(ShareEvent::LowDifficulty);
+                        match self.jobs.lock().unwrap().get(&jobid) {
+                            Some(job) => warn!(
+                                "Low difficulty share (Job id: {:?}, Difficulty: {}, Target: 0x{})",
+                                jobid,
+                                job.difficulty,
+                                hex::encode(job.target.to_be_bytes())
+                            ),
+                            None => warn!("Low difficulty share (Job id: {:?})", jobid),
+                        }
                         Ok(())
                     }
                     ErrorCode::Unauthorized => {
@@ -398,49 +650,568 @@ impl StratumHandler {
         }
     }
 
-    fn set_difficulty(&mut self, difficulty: &f32) -> Result<(), Error> {
-        let mut buf = [0u64, 0u64, 0u64, 0u64];
-        let (mantissa, exponent, _) = difficulty.recip().integer_decode();
-        let new_mantissa = mantissa * DIFFICULTY_1_TARGET.0;
-        let new_exponent = (DIFFICULTY_1_TARGET.1 + exponent) as u64;
-        let start = (new_exponent / 64) as usize;
-        let remainder = new_exponent % 64;
+    async fn notify(
+        &mut self,
+        id: String,
+        header_hash: [u64; 4],
+        timestamp: u64,
+        clean_jobs: bool,
+        miner: &mut dyn JobSink,
+    ) -> Result<(), Error> {
+        if self.count_jobs {
+            self.block_template_ctr
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |v| Some((v + 1) % 10_000))
+                .unwrap();
+        }
+        self.last_notify = Instant::now();
+        self.jobs.lock().unwrap().push(
+            Job { id: id.clone(), difficulty: self.difficulty_pool, target: self.target_pool, valid: true },
+            clean_jobs,
+        );
+        miner
+            .process_block(Some(PartialBlock {
+                id,
+                header_hash,
+                timestamp,
+                nonce: 0,
+                target: self.target_pool,
+                nonce_mask: self.nonce_mask,
+                nonce_fixed: self.nonce_fixed,
+                hash: None,
+            }))
+            .await
+    }
+
+    /// Splits a hex encoded job header (pre-pow hash followed by the timestamp, both little-endian)
+    fn parse_header(header: &str) -> Result<([u64; 4], u64), Error> {
+        let bytes = hex::decode(header)?;
+        if bytes.len() != 40 {
+            return Err(format!("Unexpected job header length: {}", bytes.len()).into());
+        }
+        let mut header_hash = [0u64; 4];
+        header_hash
+            .iter_mut()
+            .zip(bytes.chunks_exact(8))
+            .for_each(|(word, chunk)| *word = u64::from_le_bytes(chunk.try_into().unwrap()));
+        let timestamp = u64::from_le_bytes(bytes[32..].try_into().unwrap());
+        Ok((header_hash, timestamp))
+    }
+
+    async fn check_liveness(&mut self) -> Result<(), Error> {
+        if let Some(timeout) = self.liveness.notify_timeout {
+            if self.last_notify.elapsed() > timeout {
+                return Err(format!("No new job from the pool for {}s", self.last_notify.elapsed().as_secs()).into());
+            }
+        }
+
+        let timeout = self.liveness.response_timeout;
+        let expired = self.shares_stats.expire(timeout);
+        if expired > 0 {
+            warn!("{} shares left unanswered for more than {}s, counting as lost", expired, timeout.as_secs());
+        }
+        self.unanswered_shares += expired;
+        if self.liveness.max_unanswered > 0 && self.unanswered_shares >= self.liveness.max_unanswered {
+            return Err(format!("{} shares in a row left unanswered", self.unanswered_shares).into());
+        }
+
+        match (self.ping_id, self.liveness.keepalive) {
+            (Some(_), _) if self.ping_sent.elapsed() > timeout => {
+                Err(format!("Keepalive left unanswered for more than {}s", timeout.as_secs()).into())
+            }
+            (None, Some(interval)) if self.ping_sent.elapsed() > interval => {
+                let id = Some(self.last_stratum_id.fetch_add(1, Ordering::SeqCst));
+                self.ping_id = id;
+                self.ping_sent = Instant::now();
+                self.send_channel
+                    .send(StratumLine {
+                        id,
+                        payload: StratumLinePayload::StratumCommand(StratumCommand::MiningPing(vec![])),
+                        jsonrpc: None,
+                        error: None,
+                    })
+                    .await?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
 
-        buf[start] = new_mantissa << remainder; // bottom
-        if start < 3 {
-            buf[start + 1] = new_mantissa >> (64 - remainder); // top
-        } else if new_mantissa.leading_zeros() < remainder as u32 {
-            return Err("Target is too big".into());
+    /// `client.reconnect` parameters are all optional: `[host, port, wait]`
+    fn request_reconnect(&mut self, params: &[Value]) -> Result<(), Error> {
+        let number = |value: Option<&Value>| match value {
+            Some(Value::Number(n)) => n.as_u64(),
+            Some(Value::String(s)) => s.parse().ok(),
+            _ => None,
+        };
+        let (current_host, current_port) = self.address.rsplit_once(':').unwrap_or((self.address.as_str(), ""));
+        let host = match params.first() {
+            Some(Value::String(host)) if !host.is_empty() => host.as_str(),
+            _ => current_host,
+        };
+        let port = number(params.get(1)).map(|port| port.to_string()).unwrap_or_else(|| current_port.to_string());
+        let wait = Duration::from_secs(number(params.get(2)).unwrap_or(0));
+        let scheme = if self.use_ssl { "stratum+ssl" } else { "stratum+tcp" };
+        let address = format!("{}://{}:{}", scheme, host, port);
+        if !self.allow_redirect && !host.eq_ignore_ascii_case(current_host) {
+            warn!("Ignoring pool request to reconnect to {}, use --stratum-allow-redirect to follow it", address);
+            return Ok(());
         }
+        warn!("Pool requested reconnecting to {} in {}s", address, wait.as_secs());
+        self.reconnect = Some(Reconnect { address, wait });
+        Ok(())
+    }
 
-        self.target_pool = Uint256::new(buf);
+    /// Takes effect on the next `mining.notify`, the current job keeps mining at its own target
+    fn set_difficulty(&mut self, difficulty: &f64) -> Result<(), Error> {
+        self.target_pool = difficulty_to_target(*difficulty)?;
+        self.difficulty_pool = *difficulty;
         info!("Difficulty: {:?}, Target: 0x{}", difficulty, hex::encode(self.target_pool.to_be_bytes()));
         Ok(())
     }
 
+    /// Same as `set_difficulty`, for pools that send the target itself
+    fn set_target(&mut self, target: &str) -> Result<(), Error> {
+        self.target_pool = parse_target(target)?;
+        self.difficulty_pool = target_to_difficulty(self.target_pool);
+        info!("Difficulty: {:?}, Target: 0x{}", self.difficulty_pool, hex::encode(self.target_pool.to_be_bytes()));
+        Ok(())
+    }
+
     fn set_extranonce(&mut self, extranonce: &str, nonce_size: &u32) -> Result<(), Error> {
         self.extranonce = Some(extranonce.to_string());
         info!("Extra! {:?}", extranonce);
-        self.nonce_fixed = u64::from_str_radix(extranonce, 16)? << (nonce_size * 8);
+        let nonce_bits = nonce_size * 8;
+        if nonce_bits >= 64 {
+            self.nonce_fixed = 0;
+            self.nonce_mask = u64::MAX;
+        } else {
+            self.nonce_fixed = u64::from_str_radix(extranonce, 16)? << nonce_bits;
+            self.nonce_mask = (1 << nonce_bits) - 1;
+        }
         info!("Extra Done!");
-        self.nonce_mask = (1 << (nonce_size * 8)) - 1;
         Ok(())
     }
 
-    async fn log_shares(shares_info: Arc<ShareStats>) {
+    /// EthereumStratum/1.0 only sends the extranonce, the miner owns the rest of the 8 nonce bytes
+    fn set_extranonce_eth(&mut self, extranonce: &str) -> Result<(), Error> {
+        if !extranonce.len().is_multiple_of(2) || extranonce.len() > 16 {
+            return Err(format!("Invalid extranonce: {}", extranonce).into());
+        }
+        self.set_extranonce(extranonce, &(8 - extranonce.len() as u32 / 2))
+    }
+
+    async fn log_shares(session: Weak<SessionStats>) {
         let mut ticker = tokio::time::interval(LOG_RATE);
         ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
         let mut _last_instant = ticker.tick().await;
         loop {
             let _now = ticker.tick().await;
-            info!("{}", shares_info)
+            let Some(session) = session.upgrade() else { return };
+            let pool = session.pool_snapshot();
+            info!("Shares: {}Pending: {}", pool.shares, session.snapshot().pending);
+            info!("Share latency ({}): {}", pool.pool, pool.latency);
         }
     }
 }
 
+/// The host of `host:port` for TLS. Certificates are not verified, but recent OpenSSL refuses an empty server name.
+fn server_name(address: &str) -> &str {
+    let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+fn difficulty_1_target() -> BigUint {
+    BigUint::from(DIFFICULTY_1_TARGET.0) << DIFFICULTY_1_TARGET.1 as usize
+}
+
+/// Exact `DIFFICULTY_1_TARGET / difficulty`, rounded down
+pub fn difficulty_to_target(difficulty: f64) -> Result<Uint256, Error> {
+    if !difficulty.is_finite() || difficulty <= 0.0 {
+        return Err(format!("Invalid difficulty: {}", difficulty).into());
+    }
+    let (mantissa, exponent, _) = difficulty.integer_decode();
+    let target = match exponent {
+        e if e >= 0 => difficulty_1_target() / (BigUint::from(mantissa) << e as usize),
+        e => (difficulty_1_target() << e.unsigned_abs() as usize) / mantissa,
+    };
+    if target.bits() > 256 {
+        return Err("Target is too big".into());
+    }
+    let mut buf = [0u8; 32];
+    let bytes = target.to_bytes_le();
+    buf[..bytes.len()].copy_from_slice(&bytes);
+    Ok(Uint256::from_le_bytes(buf))
+}
+
+fn target_to_difficulty(target: Uint256) -> f64 {
+    let target = BigUint::from_bytes_le(&target.to_le_bytes()).to_f64().unwrap_or(f64::INFINITY);
+    difficulty_1_target().to_f64().unwrap_or(f64::INFINITY) / target
+}
+
+/// Hashes it takes on average to find a share below `target`
+fn target_to_work(target: Uint256) -> f64 {
+    match BigUint::from_bytes_le(&target.to_le_bytes()).to_f64() {
+        Some(target) if target > 0.0 => 2f64.powi(256) / target,
+        _ => 0.0,
+    }
+}
+
+/// Parses a big-endian hex target, as sent by `mining.set_target`
+fn parse_target(target: &str) -> Result<Uint256, Error> {
+    let target = target.trim_start_matches("0x");
+    if target.len() > 64 {
+        return Err(format!("Target is too big: {}", target).into());
+    }
+    let mut buf = [0u8; 32];
+    hex::decode_to_slice(format!("{:0>64}", target), &mut buf)?;
+    Ok(Uint256::from_be_bytes(buf))
+}
+
 impl Drop for StratumHandler {
     fn drop(&mut self) {
         self.log_handler.abort();
         self.block_handle.abort()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::mock_pool::{share_hash, MockPool, MockPoolConfig};
+    use super::recording::{Direction, RecordedLine};
+    use super::statum_codec::ErrorCode;
+    use super::{
+        difficulty_to_target, parse_target, server_name, target_to_difficulty, target_to_work, Job, JobHistory,
+        StratumDialect, StratumHandler, StratumLiveness, StratumOptions, JOB_HISTORY,
+    };
+    use crate::client::Client;
+    use crate::pow::BlockSeed;
+    use crate::stats::Statistics;
+    use crate::{Error, MinerManager, Uint256};
+    use futures::Future;
+    use karlsen_miner::PluginManager;
+    use std::sync::atomic::AtomicU16;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn options(dialect: StratumDialect) -> StratumOptions {
+        StratumOptions {
+            dialect,
+            liveness: StratumLiveness {
+                notify_timeout: None,
+                response_timeout: Duration::from_secs(30),
+                max_unanswered: 0,
+                keepalive: None,
+            },
+            submit_stale: false,
+            allow_redirect: false,
+            record: None,
+        }
+    }
+
+    async fn connect(
+        pool: &MockPool,
+        dialect: StratumDialect,
+        block_template_ctr: Option<Arc<AtomicU16>>,
+        statistics: &Statistics,
+    ) -> Box<StratumHandler> {
+        let address = pool.address.to_string();
+        let use_ssl = pool.url().starts_with("stratum+ssl");
+        StratumHandler::connect(
+            address,
+            "karlsen:miner".into(),
+            false,
+            block_template_ctr,
+            use_ssl,
+            options(dialect),
+            None,
+            statistics,
+        )
+        .await
+        .unwrap()
+    }
+
+    /// Runs the handler until `script` is done, or returns what `listen` ended with
+    async fn listen_while(client: &mut StratumHandler, script: impl Future<Output = ()>) -> Option<Result<(), Error>> {
+        let mut miner_manager =
+            MinerManager::new(client.get_block_channel(), &PluginManager::new(), Default::default());
+        miner_manager.discard_jobs();
+        tokio::select! {
+            result = client.listen(&mut miner_manager) => Some(result),
+            _ = script => None,
+        }
+    }
+
+    /// A share as a worker would have found it, `nonce_mask` covers the part the miner owns
+    fn share(id: &str, nonce: u64, nonce_mask: u64) -> BlockSeed {
+        BlockSeed::PartialBlock {
+            id: id.into(),
+            header_hash: [0; 4],
+            timestamp: 0,
+            nonce,
+            target: Default::default(),
+            nonce_mask,
+            nonce_fixed: 0,
+            hash: None,
+        }
+    }
+
+    fn target_hex(difficulty: f64) -> String {
+        hex::encode(difficulty_to_target(difficulty).unwrap().to_be_bytes())
+    }
+
+    #[test]
+    fn test_difficulty_to_target() {
+        assert_eq!(target_hex(1.0), "00000000ffff0000000000000000000000000000000000000000000000000000");
+        assert_eq!(target_hex(2.0), "000000007fff8000000000000000000000000000000000000000000000000000");
+        assert_eq!(target_hex(0.5), "00000001fffe0000000000000000000000000000000000000000000000000000");
+        assert_eq!(target_hex(16384.0), "000000000003fffc000000000000000000000000000000000000000000000000");
+        assert_eq!(target_hex(6442450944.0), "0000000000000000aaaa00000000000000000000000000000000000000000000");
+        // Not representable as an f32 reciprocal
+        assert_eq!(target_hex(1e9), "00000000000000044b7eae86bb9b77145e86c9077061bcde6d333434e5e5cd79");
+    }
+
+    #[test]
+    fn test_invalid_difficulty() {
+        assert!(difficulty_to_target(0.0).is_err());
+        assert!(difficulty_to_target(-1.0).is_err());
+        assert!(difficulty_to_target(f64::NAN).is_err());
+        assert!(difficulty_to_target(1e-80).is_err());
+    }
+
+    #[test]
+    fn test_parse_target() {
+        let target = "00000000ffff0000000000000000000000000000000000000000000000000000";
+        assert_eq!(parse_target(target).unwrap(), difficulty_to_target(1.0).unwrap());
+        assert_eq!(parse_target(&format!("0x{}", target)).unwrap(), difficulty_to_target(1.0).unwrap());
+        assert_eq!(
+            parse_target("3fffc000000000000000000000000000000000000000000000000").unwrap(),
+            difficulty_to_target(16384.0).unwrap()
+        );
+        assert!(parse_target(&format!("00{}", target)).is_err());
+        assert!(parse_target("zz").is_err());
+    }
+
+    #[test]
+    fn test_server_name() {
+        assert_eq!(server_name("pool.example.com:5555"), "pool.example.com");
+        assert_eq!(server_name("[::1]:5555"), "::1");
+        assert_eq!(server_name("pool.example.com"), "pool.example.com");
+    }
+
+    #[test]
+    fn test_target_to_difficulty() {
+        for difficulty in [0.5, 1.0, 16384.0, 1e9] {
+            let recovered = target_to_difficulty(difficulty_to_target(difficulty).unwrap());
+            assert!((recovered - difficulty).abs() / difficulty < 1e-12, "{} != {}", recovered, difficulty);
+        }
+        // A difficulty 1 share takes 2^256 / (0xffff * 2^208) hashes
+        let work = target_to_work(difficulty_to_target(1.0).unwrap());
+        assert!((work - 2f64.powi(48) / 65535.0).abs() < 1.0, "{}", work);
+        assert_eq!(target_to_work(Uint256::default()), 0.0);
+    }
+
+    #[test]
+    fn test_job_history() {
+        let job = |id: u32| Job { id: id.to_string(), difficulty: 1.0, target: Uint256::default(), valid: true };
+        let mut jobs = JobHistory::default();
+        jobs.push(job(1), false);
+        jobs.push(job(2), false);
+        assert!(jobs.is_valid("1") && jobs.is_valid("2") && jobs.is_valid("0"));
+
+        jobs.push(job(3), true);
+        assert!(!jobs.is_valid("1") && !jobs.is_valid("2") && jobs.is_valid("3"));
+
+        jobs.invalidate("3");
+        assert!(!jobs.is_valid("3"));
+
+        (4..4 + JOB_HISTORY as u32).for_each(|id| jobs.push(job(id), false));
+        assert!(jobs.get("1").is_none() && !jobs.is_valid("1"));
+        assert!(jobs.get("3").is_none() && !jobs.is_valid("3"));
+    }
+
+    #[tokio::test]
+    async fn test_replay() {
+        let recording = [
+            (Direction::Send, r#"{"id":0,"method":"mining.subscribe","params":["karlsen-miner/0.0.0"]}"#),
+            (Direction::Recv, r#"{"id":0,"result":true,"error":null}"#),
+            (Direction::Send, r#"{"id":1,"method":"mining.authorize","params":["karlsen:miner"]}"#),
+            (Direction::Recv, r#"{"id":1,"result":true,"error":null}"#),
+            (Direction::Recv, r#"{"id":null,"method":"mining.set_difficulty","params":[1.0]}"#),
+            (Direction::Recv, r#"{"id":null,"method":"mining.notify","params":["1",[1,2,3,4],1234]}"#),
+            (Direction::Send, r#"{"id":2,"method":"mining.submit","params":["karlsen:miner","1","00000000000000ff"]}"#),
+            (Direction::Recv, r#"{"id":2,"result":true,"error":null}"#),
+            // The same nonce again is dropped locally, so the replay expects it but nothing is sent
+            (Direction::Send, r#"{"id":3,"method":"mining.submit","params":["karlsen:miner","1","00000000000000ff"]}"#),
+        ]
+        .into_iter()
+        .map(|(direction, line)| RecordedLine { timestamp: 0, direction, line: line.into() })
+        .collect();
+        let (mut client, report) = StratumHandler::replay(
+            recording,
+            "karlsen:miner".into(),
+            options(StratumDialect::Default),
+            &Default::default(),
+        );
+        client.register().await.unwrap();
+        let mut miner_manager =
+            MinerManager::new(client.get_block_channel(), &PluginManager::new(), Default::default());
+        miner_manager.discard_jobs();
+        assert!(client.listen(&mut miner_manager).await.is_err());
+
+        let report = report.await.unwrap();
+        assert_eq!(report.replayed, 9);
+        assert_eq!(report.divergences.len(), 1, "{:?}", report.divergences);
+        assert!(report.divergences[0].contains("nothing was sent"));
+    }
+
+    #[tokio::test]
+    async fn test_mock_pool_shares() {
+        let pool = MockPool::start(MockPoolConfig { extranonce_size: 2, ..Default::default() }).await;
+        let statistics = Statistics::default();
+        let mut client = connect(&pool, StratumDialect::Default, None, &statistics).await;
+        client.register().await.unwrap();
+        let worker = client.get_block_channel();
+        let script = async {
+            pool.wait_for_authorized(1).await;
+            let job = pool.notify(false);
+            worker.send(share(&job, 0xa5a5_0000_0000_0001, u64::MAX)).await.unwrap();
+            pool.wait_for_submissions(1).await;
+            // The same nonce again never reaches the pool
+            worker.send(share(&job, 0xa5a5_0000_0000_0001, u64::MAX)).await.unwrap();
+            pool.script_error(ErrorCode::JobNotFound);
+            worker.send(share(&job, 0xa5a5_0000_0000_0002, u64::MAX)).await.unwrap();
+            pool.wait_for_submissions(2).await;
+            // The pool dropped the job, so later shares for it are not sent either
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            worker.send(share(&job, 0xa5a5_0000_0000_0003, u64::MAX)).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        };
+        let result = listen_while(&mut client, script).await;
+        assert!(result.is_none(), "{:?}", result);
+
+        let submissions = pool.submissions();
+        assert_eq!(submissions.len(), 2);
+        assert_eq!(submissions[0].worker, "karlsen:miner");
+        assert_eq!(submissions[0].job_id, "1");
+        assert_eq!(submissions[0].nonce, "a5a5000000000001");
+        assert!(submissions[0].error.is_none());
+        assert!(matches!(submissions[1].error, Some(ErrorCode::JobNotFound)));
+
+        let shares = statistics.snapshot().sessions[0].shares;
+        assert_eq!((shares.accepted, shares.stale, shares.dropped_duplicate, shares.suppressed), (1, 1, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_mock_pool_low_difficulty() {
+        let difficulty = 2f64.powi(-31);
+        let pool = MockPool::start(MockPoolConfig { difficulty, ..Default::default() }).await;
+        let statistics = Statistics::default();
+        let mut client = connect(&pool, StratumDialect::Default, None, &statistics).await;
+        client.register().await.unwrap();
+        let worker = client.get_block_channel();
+        // About half of the hashes meet a target this high, the pool tells them apart
+        let target = difficulty_to_target(difficulty).unwrap();
+        let meets = |nonce: &u64| share_hash("1", *nonce) <= target;
+        let low = (1..).find(|nonce| !meets(nonce)).unwrap();
+        let valid = (1..).find(meets).unwrap();
+        let script = async {
+            pool.wait_for_authorized(1).await;
+            let job = pool.notify(false);
+            worker.send(share(&job, low, u64::MAX)).await.unwrap();
+            worker.send(share(&job, valid, u64::MAX)).await.unwrap();
+            pool.wait_for_submissions(2).await;
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        };
+        let result = listen_while(&mut client, script).await;
+        assert!(result.is_none(), "{:?}", result);
+
+        let submissions = pool.submissions();
+        assert!(matches!(submissions[0].error, Some(ErrorCode::LowDifficultyShare)));
+        assert!(submissions[1].error.is_none());
+        let shares = statistics.snapshot().sessions[0].shares;
+        assert_eq!((shares.accepted, shares.low_difficulty), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_mock_pool_ethstratum_tls() {
+        let config =
+            MockPoolConfig { dialect: StratumDialect::EthStratum, extranonce_size: 3, tls: true, ..Default::default() };
+        let pool = MockPool::start(config).await;
+        let mut client = connect(&pool, StratumDialect::EthStratum, None, &Default::default()).await;
+        client.register().await.unwrap();
+        let worker = client.get_block_channel();
+        let nonce_mask = (1 << 40) - 1;
+        let script = async {
+            pool.wait_for_authorized(1).await;
+            let job = pool.notify(true);
+            worker.send(share(&job, 0xa5a5a5_0000000001, nonce_mask)).await.unwrap();
+            pool.wait_for_submissions(1).await;
+            // A clean job makes shares for the previous one stale
+            pool.notify(true);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            worker.send(share(&job, 0xa5a5a5_0000000002, nonce_mask)).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        };
+        let result = listen_while(&mut client, script).await;
+        assert!(result.is_none(), "{:?}", result);
+
+        let submissions = pool.submissions();
+        assert_eq!(submissions.len(), 1);
+        assert_eq!(submissions[0].nonce, "0000000001");
+        assert!(submissions[0].error.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_mock_pool_unauthorized() {
+        let pool = MockPool::start(MockPoolConfig::default()).await;
+        let mut client = connect(&pool, StratumDialect::Default, None, &Default::default()).await;
+        client.register().await.unwrap();
+        let worker = client.get_block_channel();
+        let script = async {
+            pool.wait_for_authorized(1).await;
+            let job = pool.notify(false);
+            pool.script_error(ErrorCode::Unauthorized);
+            worker.send(share(&job, 1, u64::MAX)).await.unwrap();
+            std::future::pending::<()>().await;
+        };
+        assert!(matches!(listen_while(&mut client, script).await, Some(Err(_))));
+    }
+
+    #[tokio::test]
+    async fn test_mock_pool_reconnect() {
+        let pool = MockPool::start(MockPoolConfig::default()).await;
+        let mut client = connect(&pool, StratumDialect::Default, None, &Default::default()).await;
+        client.register().await.unwrap();
+        let script = async {
+            pool.wait_for_authorized(1).await;
+            // Another host would get the payout address, only the same one is followed
+            pool.reconnect("pool.example.com", 3333, 0);
+            pool.reconnect("127.0.0.1", 3333, 0);
+            std::future::pending::<()>().await;
+        };
+        let result = listen_while(&mut client, script).await;
+        assert!(matches!(result, Some(Ok(()))), "{:?}", result);
+        let reconnect = client.reconnect_request().unwrap();
+        assert_eq!(reconnect.address, "stratum+tcp://127.0.0.1:3333");
+        assert_eq!(reconnect.wait, Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_mock_pool_devfund() {
+        let pool = MockPool::start(MockPoolConfig::default()).await;
+        let block_template_ctr = Arc::new(AtomicU16::new(0));
+        let mut client = connect(&pool, StratumDialect::Default, Some(block_template_ctr), &Default::default()).await;
+        client.add_devfund("karlsen:devfund".into(), 1);
+        client.register().await.unwrap();
+        let script = async {
+            pool.wait_for_authorized(1).await;
+            // Every job counts towards the devfund share, once it is used up the handler hands back
+            pool.notify(false);
+            pool.notify(false);
+            std::future::pending::<()>().await;
+        };
+        let result = listen_while(&mut client, script).await;
+        assert!(matches!(result, Some(Ok(()))), "{:?}", result);
+        assert_eq!(pool.authorized(), vec!["karlsen:devfund".to_string()]);
+    }
+}
@@ -0,0 +1,298 @@
+//! A stand-in stratum pool, so `StratumHandler` can be tested end to end over a real socket.
+//! Shares are checked against the pool target with `share_hash`, the real proof of work needs the DAG.
+
+use futures::prelude::*;
+use native_tls::{Identity, TlsAcceptor as NativeTlsAcceptor};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio_native_tls::TlsAcceptor;
+use tokio_util::codec::{Framed, LinesCodec};
+
+use crate::client::stratum::statum_codec::ErrorCode;
+use crate::client::stratum::{difficulty_to_target, StratumDialect};
+use crate::pow::HeaderHasher;
+use crate::target::Uint256;
+
+const CERTIFICATE: &[u8] = include_bytes!("testdata/mock_pool.crt");
+const PRIVATE_KEY: &[u8] = include_bytes!("testdata/mock_pool.key");
+const WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug)]
+pub struct MockPoolConfig {
+    pub dialect: StratumDialect,
+    /// Low enough by default that nearly every share meets it
+    pub difficulty: f64,
+    /// Leading nonce bytes fixed by the pool
+    pub extranonce_size: usize,
+    pub tls: bool,
+}
+
+impl Default for MockPoolConfig {
+    fn default() -> Self {
+        Self { dialect: StratumDialect::Default, difficulty: 2f64.powi(-32), extranonce_size: 0, tls: false }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Submission {
+    pub worker: String,
+    pub job_id: String,
+    pub nonce: String,
+    pub error: Option<ErrorCode>,
+}
+
+#[derive(Default)]
+struct PoolState {
+    jobs: Vec<String>,
+    authorized: Vec<String>,
+    submissions: Vec<Submission>,
+    submitted: HashSet<(String, String)>,
+    scripted_errors: Vec<ErrorCode>,
+    clients: Vec<UnboundedSender<Value>>,
+}
+
+pub struct MockPool {
+    pub address: SocketAddr,
+    config: MockPoolConfig,
+    state: Arc<Mutex<PoolState>>,
+    handle: JoinHandle<()>,
+}
+
+impl MockPool {
+    pub async fn start(config: MockPoolConfig) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(PoolState::default()));
+        let acceptor = config.tls.then(|| {
+            let identity = Identity::from_pkcs8(CERTIFICATE, PRIVATE_KEY).unwrap();
+            TlsAcceptor::from(NativeTlsAcceptor::new(identity).unwrap())
+        });
+        let handle = tokio::spawn({
+            let (config, state) = (config.clone(), state.clone());
+            async move {
+                while let Ok((socket, _)) = listener.accept().await {
+                    let (config, state) = (config.clone(), state.clone());
+                    match acceptor.clone() {
+                        Some(acceptor) => tokio::spawn(async move {
+                            if let Ok(socket) = acceptor.accept(socket).await {
+                                serve(socket, config, state).await
+                            }
+                        }),
+                        None => tokio::spawn(serve(socket, config, state)),
+                    };
+                }
+            }
+        });
+        Self { address, config, state, handle }
+    }
+
+    pub fn url(&self) -> String {
+        let scheme = if self.config.tls { "stratum+ssl" } else { "stratum+tcp" };
+        format!("{}://{}", scheme, self.address)
+    }
+
+    /// Sends a new job to every connected miner and returns its id
+    pub fn notify(&self, clean_jobs: bool) -> String {
+        let mut state = self.state.lock().unwrap();
+        let id = (state.jobs.len() + 1).to_string();
+        state.jobs.push(id.clone());
+        let notify = notify_line(&self.config, &id, clean_jobs);
+        state.clients.iter().for_each(|client| _ = client.send(notify.clone()));
+        id
+    }
+
+    pub fn reconnect(&self, host: &str, port: u16, wait: u64) {
+        let reconnect = json!({"id": null, "method": "client.reconnect", "params": [host, port, wait]});
+        self.state.lock().unwrap().clients.iter().for_each(|client| _ = client.send(reconnect.clone()));
+    }
+
+    /// Answers the next submission with `error`, whether or not the share is valid
+    pub fn script_error(&self, error: ErrorCode) {
+        self.state.lock().unwrap().scripted_errors.push(error);
+    }
+
+    pub fn authorized(&self) -> Vec<String> {
+        self.state.lock().unwrap().authorized.clone()
+    }
+
+    pub fn submissions(&self) -> Vec<Submission> {
+        self.state.lock().unwrap().submissions.clone()
+    }
+
+    pub async fn wait_for_authorized(&self, count: usize) {
+        self.wait_until(|state| state.authorized.len() >= count).await
+    }
+
+    pub async fn wait_for_submissions(&self, count: usize) {
+        self.wait_until(|state| state.submissions.len() >= count).await
+    }
+
+    async fn wait_until(&self, condition: impl Fn(&PoolState) -> bool) {
+        let started = Instant::now();
+        while !condition(&self.state.lock().unwrap()) {
+            assert!(started.elapsed() < WAIT_TIMEOUT, "Timed out waiting for the miner");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
+
+impl Drop for MockPool {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn serve<S: AsyncRead + AsyncWrite + Unpin>(socket: S, config: MockPoolConfig, state: Arc<Mutex<PoolState>>) {
+    let (mut sink, mut stream) = Framed::new(socket, LinesCodec::new()).split();
+    let (send, mut recv) = mpsc::unbounded_channel::<Value>();
+    state.lock().unwrap().clients.push(send.clone());
+    let mut connection = Connection { config, state, send, subscribed: false, worker: None };
+    loop {
+        tokio::select! {
+            line = stream.next() => match line {
+                Some(Ok(line)) => match serde_json::from_str(&line) {
+                    Ok(request) => connection.handle(request),
+                    Err(_) => return,
+                },
+                _ => return,
+            },
+            Some(line) = recv.recv() => {
+                if sink.send(line.to_string()).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+struct Connection {
+    config: MockPoolConfig,
+    state: Arc<Mutex<PoolState>>,
+    send: UnboundedSender<Value>,
+    subscribed: bool,
+    worker: Option<String>,
+}
+
+impl Connection {
+    fn handle(&mut self, request: Value) {
+        let id = request["id"].clone();
+        let params = &request["params"];
+        let extranonce = extranonce(&self.config);
+        match request["method"].as_str().unwrap_or_default() {
+            "mining.subscribe" => {
+                self.subscribed = true;
+                match self.config.dialect {
+                    StratumDialect::Default => {
+                        self.reply(id, json!(true));
+                        let nonce_size = 8 - self.config.extranonce_size;
+                        self.push(
+                            json!({"id": null, "method": "mining.set_extranonce", "params": [extranonce, nonce_size]}),
+                        );
+                    }
+                    StratumDialect::EthStratum => {
+                        self.reply(id, json!([["mining.notify", "0", "EthereumStratum/1.0.0"], extranonce]))
+                    }
+                }
+            }
+            "mining.authorize" if !self.subscribed => self.error(id, ErrorCode::NotSubscribed, "Not subscribed"),
+            "mining.authorize" => {
+                let worker = params[0].as_str().unwrap_or_default().to_string();
+                self.worker = Some(worker.clone());
+                self.reply(id, json!(true));
+                self.push(json!({"id": null, "method": "mining.set_difficulty", "params": [self.config.difficulty]}));
+                let mut state = self.state.lock().unwrap();
+                state.authorized.push(worker);
+                if let Some(job) = state.jobs.last() {
+                    _ = self.send.send(notify_line(&self.config, job, true));
+                }
+            }
+            "mining.extranonce.subscribe" | "mining.ping" => self.reply(id, json!(true)),
+            "mining.submit" => {
+                let job_id = params[1].as_str().unwrap_or_default().to_string();
+                let nonce = params[2].as_str().unwrap_or_default().to_string();
+                let error = self.validate(&job_id, &nonce, &extranonce);
+                let mut state = self.state.lock().unwrap();
+                let worker = params[0].as_str().unwrap_or_default().to_string();
+                state.submissions.push(Submission { worker, job_id, nonce, error: error.clone() });
+                drop(state);
+                match error {
+                    Some(error) => self.error(id, error.clone(), &error.to_string()),
+                    None => self.reply(id, json!(true)),
+                }
+            }
+            method => self.error(id, ErrorCode::Unknown, &format!("Method not supported: {}", method)),
+        }
+    }
+
+    fn validate(&self, job_id: &str, nonce: &str, extranonce: &str) -> Option<ErrorCode> {
+        let mut state = self.state.lock().unwrap();
+        if !state.scripted_errors.is_empty() {
+            return Some(state.scripted_errors.remove(0));
+        }
+        if self.worker.is_none() {
+            return Some(ErrorCode::Unauthorized);
+        }
+        if !state.jobs.iter().any(|job| job == job_id) {
+            return Some(ErrorCode::JobNotFound);
+        }
+        // EthereumStratum/1.0 miners leave out the extranonce, everyone else sends all 8 bytes
+        let full_nonce = match self.config.dialect {
+            StratumDialect::Default => nonce.to_string(),
+            StratumDialect::EthStratum => format!("{}{}", extranonce, nonce),
+        };
+        let parsed = u64::from_str_radix(&full_nonce, 16);
+        let parsed = match parsed {
+            Ok(parsed) if full_nonce.len() == 16 && full_nonce.starts_with(extranonce) => parsed,
+            _ => return Some(ErrorCode::Unknown),
+        };
+        if !state.submitted.insert((job_id.to_string(), full_nonce)) {
+            return Some(ErrorCode::DuplicateShare);
+        }
+        if share_hash(job_id, parsed) > difficulty_to_target(self.config.difficulty).unwrap() {
+            return Some(ErrorCode::LowDifficultyShare);
+        }
+        None
+    }
+
+    fn reply(&self, id: Value, result: Value) {
+        self.push(json!({"id": id, "result": result, "error": null}));
+    }
+
+    fn error(&self, id: Value, code: ErrorCode, message: &str) {
+        self.push(json!({"id": id, "result": null, "error": [code, message, null]}));
+    }
+
+    fn push(&self, line: Value) {
+        _ = self.send.send(line);
+    }
+}
+
+/// Stands in for the proof of work of a share: any hash that spreads evenly compares with the target the same way
+pub fn share_hash(job_id: &str, nonce: u64) -> Uint256 {
+    let mut hasher = HeaderHasher::new();
+    hasher.write(job_id);
+    hasher.write(nonce.to_le_bytes());
+    hasher.finalize()
+}
+
+fn extranonce(config: &MockPoolConfig) -> String {
+    "a5".repeat(config.extranonce_size)
+}
+
+fn notify_line(config: &MockPoolConfig, id: &str, clean_jobs: bool) -> Value {
+    let timestamp = 1_700_000_000_000u64 + id.parse::<u64>().unwrap_or_default();
+    let params = match config.dialect {
+        StratumDialect::Default => json!([id, [1, 2, 3, 4], timestamp]),
+        StratumDialect::EthStratum => {
+            json!([id, format!("{}{}", "00".repeat(32), hex::encode(timestamp.to_le_bytes())), clean_jobs])
+        }
+    };
+    json!({"id": null, "method": "mining.notify", "params": params})
+}
@@ -0,0 +1,158 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::time::timeout;
+
+use crate::client::stratum::statum_codec::{NewLineJsonCodecError, StratumLine};
+use crate::pow::BlockSeed;
+use crate::Error;
+
+/// How long the replay waits for the handler to send what the recording says was sent
+const REPLAY_RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Send,
+    Recv,
+}
+
+/// One raw stratum line, as it went over the wire
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecordedLine {
+    /// Milliseconds since the unix epoch
+    pub timestamp: u64,
+    pub direction: Direction,
+    pub line: String,
+}
+
+/// Appends every stratum line of a connection to a JSONL file
+#[derive(Clone)]
+pub struct StratumRecorder {
+    file: Arc<Mutex<LineWriter<File>>>,
+}
+
+impl StratumRecorder {
+    pub fn create(path: &Path) -> Result<Self, Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file: Arc::new(Mutex::new(LineWriter::new(file))) })
+    }
+
+    pub fn record(&self, direction: Direction, line: &str) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        let entry = RecordedLine { timestamp, direction, line: line.to_string() };
+        let result = serde_json::to_string(&entry)
+            .map_err(Error::from)
+            .and_then(|json| writeln!(self.file.lock().unwrap(), "{}", json).map_err(Error::from));
+        if let Err(e) = result {
+            warn!("Failed recording stratum line: {}", e);
+        }
+    }
+}
+
+pub fn load(path: &Path) -> Result<Vec<RecordedLine>, Error> {
+    let reader = BufReader::new(File::open(path)?);
+    reader
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|(i, line)| Ok(serde_json::from_str(&line?).map_err(|e| format!("line {}: {}", i + 1, e))?))
+        .collect()
+}
+
+/// Outcome of a replay: lines the handler sent differently than recorded
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    pub replayed: usize,
+    pub divergences: Vec<String>,
+}
+
+/// Plays the pool side of a recording to a handler. Recorded share submissions are fed to the
+/// handler's block channel as if a worker had found them, and everything the handler sends is
+/// compared with what was sent during the recording.
+pub async fn replay(
+    recording: Vec<RecordedLine>,
+    pool: Sender<Result<StratumLine, NewLineJsonCodecError>>,
+    mut sent: Receiver<StratumLine>,
+    worker: Sender<BlockSeed>,
+) -> ReplayReport {
+    let mut report = ReplayReport::default();
+    for recorded in recording {
+        report.replayed += 1;
+        match recorded.direction {
+            Direction::Recv => {
+                let line = serde_json::from_str::<StratumLine>(&recorded.line)
+                    .map_err(|e| (e.to_string(), recorded.line.clone()).into());
+                if pool.send(line).await.is_err() {
+                    report.divergences.push("Handler closed the connection before the recording ended".into());
+                    break;
+                }
+            }
+            Direction::Send => {
+                let expected: Value = match serde_json::from_str(&recorded.line) {
+                    Ok(expected) => expected,
+                    Err(e) => {
+                        report.divergences.push(format!("Unreadable sent line {}: {}", recorded.line, e));
+                        continue;
+                    }
+                };
+                if let Some(share) = recorded_share(&expected) {
+                    if worker.send(share).await.is_err() {
+                        report.divergences.push("Block channel closed before the recording ended".into());
+                        break;
+                    }
+                }
+                match timeout(REPLAY_RESPONSE_TIMEOUT, sent.recv()).await {
+                    Ok(Some(line)) => {
+                        let actual = serde_json::to_value(&line).unwrap_or_default();
+                        if !same_request(&expected, &actual) {
+                            report.divergences.push(format!("Expected {} but sent {}", expected, actual));
+                        }
+                    }
+                    Ok(None) => {
+                        report.divergences.push("Handler closed the connection before the recording ended".into());
+                        break;
+                    }
+                    Err(_) => report.divergences.push(format!("Expected {} but nothing was sent", expected)),
+                }
+            }
+        }
+    }
+    info!("Replayed {} lines, {} divergences", report.replayed, report.divergences.len());
+    report
+}
+
+/// Submissions carry (worker, job id, nonce), which is all a simulated worker needs to find the share again
+fn recorded_share(line: &Value) -> Option<BlockSeed> {
+    if line["method"] != "mining.submit" {
+        return None;
+    }
+    let id = line["params"][1].as_str()?.to_string();
+    let nonce = line["params"][2].as_str()?.trim_start_matches("0x");
+    let nonce_mask = match nonce.len() {
+        len if len >= 16 => u64::MAX,
+        len => (1u64 << (len * 4)) - 1,
+    };
+    Some(BlockSeed::PartialBlock {
+        id,
+        header_hash: [0; 4],
+        timestamp: 0,
+        nonce: u64::from_str_radix(nonce, 16).ok()?,
+        target: Default::default(),
+        nonce_mask,
+        nonce_fixed: 0,
+        hash: None,
+    })
+}
+
+/// Ids and agent strings are allowed to differ, methods and share parameters are not
+fn same_request(expected: &Value, actual: &Value) -> bool {
+    expected["method"] == actual["method"]
+        && (expected["method"] != "mining.submit" || expected["params"] == actual["params"])
+}
@@ -7,6 +7,8 @@ use std::fmt::{Display, Formatter};
 use std::{fmt, io};
 use tokio_util::codec::{Decoder, Encoder, LinesCodec};
 
+use crate::client::stratum::recording::{Direction, StratumRecorder};
+
 #[derive(Serialize_repr, Deserialize_repr, Debug, Clone)]
 #[repr(u8)]
 pub enum ErrorCode {
@@ -41,8 +43,10 @@ pub(crate) struct StratumError {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(untagged)]
+#[allow(clippy::enum_variant_names)]
 pub(crate) enum MiningNotify {
     MiningNotifyShort((String, [u64; 4], u64)),
+    MiningNotifyEth((String, String, bool)),
     MiningNotifyLong((String, String, String, String, Vec<String>, String, String, String, bool)),
 }
 
@@ -73,11 +77,23 @@ pub(crate) enum StratumCommand {
     #[serde(rename = "mining.set_extranonce", alias = "set_extranonce")]
     SetExtranonce(SetExtranonce),
     #[serde(rename = "mining.set_difficulty")]
-    MiningSetDifficulty((f32,)),
+    MiningSetDifficulty((f64,)),
+    #[serde(rename = "mining.set_target")]
+    MiningSetTarget((String,)),
     #[serde(rename = "mining.notify")]
     MiningNotify(MiningNotify),
     #[serde(rename = "mining.subscribe")]
     Subscribe(MiningSubscribe),
+    #[serde(rename = "mining.extranonce.subscribe")]
+    ExtranonceSubscribe(Vec<String>),
+    #[serde(rename = "mining.ping")]
+    MiningPing(Vec<String>),
+    #[serde(rename = "client.reconnect")]
+    ClientReconnect(Vec<Value>),
+    #[serde(rename = "client.show_message")]
+    ClientShowMessage((String,)),
+    #[serde(rename = "client.get_version")]
+    ClientGetVersion(Option<Vec<Value>>),
     #[serde(rename = "mining.authorize")]
     Authorize((String, String)),
     #[serde(rename = "mining.submit")]
@@ -95,13 +111,22 @@ pub(crate) enum StratumResult {
     Plain(Option<bool>),
     Eth((bool, String)),
     Subscribe((Option<Vec<(String, String)>>, String, u32)),
+    SubscribeEth((Vec<String>, String)),
+    Text(String),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(untagged)]
 pub(crate) enum StratumLinePayload {
     StratumCommand(StratumCommand),
-    StratumResult { result: StratumResult },
+    StratumResult {
+        result: StratumResult,
+    },
+    Unknown {
+        method: String,
+        #[serde(default)]
+        params: Option<Value>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -150,11 +175,22 @@ impl From<(String, String)> for NewLineJsonCodecError {
 
 pub(crate) struct NewLineJsonCodec {
     lines_codec: LinesCodec,
+    recorder: Option<StratumRecorder>,
 }
 
 impl NewLineJsonCodec {
     pub fn new() -> Self {
-        Self { lines_codec: LinesCodec::new() }
+        Self { lines_codec: LinesCodec::new(), recorder: None }
+    }
+
+    pub fn with_recorder(recorder: Option<StratumRecorder>) -> Self {
+        Self { lines_codec: LinesCodec::new(), recorder }
+    }
+
+    fn record(&self, direction: Direction, line: &str) {
+        if let Some(ref recorder) = self.recorder {
+            recorder.record(direction, line);
+        }
     }
 }
 
@@ -170,6 +206,7 @@ impl Decoder for NewLineJsonCodec {
         */
         match self.lines_codec.decode(src) {
             Ok(Some(s)) => {
+                self.record(Direction::Recv, &s);
                 serde_json::from_str::<StratumLine>(s.as_str()).map_err(|e| (e.to_string(), s).into()).map(Some)
             }
             Err(_) => Err(NewLineJsonCodecError::LineSplitError),
@@ -179,7 +216,10 @@ impl Decoder for NewLineJsonCodec {
 
     fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
         match self.lines_codec.decode_eof(buf) {
-            Ok(Some(s)) => serde_json::from_str(s.as_str()).map_err(|e| (e.to_string(), s).into()),
+            Ok(Some(s)) => {
+                self.record(Direction::Recv, &s);
+                serde_json::from_str(s.as_str()).map_err(|e| (e.to_string(), s).into())
+            }
             Err(_) => Err(NewLineJsonCodecError::LineSplitError),
             _ => Ok(None),
         }
@@ -191,7 +231,10 @@ impl Encoder<StratumLine> for NewLineJsonCodec {
 
     fn encode(&mut self, item: StratumLine, dst: &mut BytesMut) -> Result<(), Self::Error> {
         match serde_json::to_string(&item) {
-            Ok(json) => self.lines_codec.encode(json, dst).map_err(|_| NewLineJsonCodecError::LineEncodeError),
+            Ok(json) => {
+                self.record(Direction::Send, &json);
+                self.lines_codec.encode(json, dst).map_err(|_| NewLineJsonCodecError::LineEncodeError)
+            }
             Err(e) => {
                 error!("Error! {:?}", e);
                 Err(NewLineJsonCodecError::JsonEncodeError)
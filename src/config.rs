@@ -0,0 +1,313 @@
+//! `--config` file. Keys are the long names of the command line flags, host and plugin alike, e.g.
+//! `mining-address = "karlsen:..."`, `pool-split = ["stratum+tcp://a:5555=60", "stratum+tcp://b:5555=40"]` or
+//! `cuda-workload = [64, 32]`. Flags given on the command line take precedence over the file.
+
+use clap::{App, Arg, FromArgMatches, IntoApp};
+use log::{debug, error, info, warn};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use toml::{Spanned, Value};
+
+use crate::cli::Opt;
+use crate::logger;
+use crate::Error;
+
+/// The settings the miner runs with. Sending new ones makes it reconnect with them, settings read where they
+/// are used, such as the watchdog's, are replaced without notifying.
+pub type Settings = Arc<watch::Sender<Arc<Opt>>>;
+
+const WATCH_RATE: Duration = Duration::from_secs(5);
+/// Only read when the miner starts, changing them in the file needs a restart
+const STARTUP_ONLY: [&str; 4] = ["config", "api-bind", "control-bind", "stratum-replay"];
+/// What the client connects with, besides the `stratum-` settings. Changing them needs a reconnect.
+const CONNECTION: [&str; 9] = [
+    "mining-address",
+    "karlsend-address",
+    "port",
+    "testnet",
+    "devnet",
+    "devfund-percent",
+    "mine-when-not-synced",
+    "pool-split",
+    "proxy",
+];
+
+#[derive(Debug)]
+struct Entry {
+    key: String,
+    line: usize,
+    args: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    entries: Vec<Entry>,
+}
+
+/// The `--config` path, looked up before clap parses anything since the file adds to the arguments
+pub fn path(cli: &[OsString]) -> Option<PathBuf> {
+    let mut args = cli.iter().skip(1).map(|arg| arg.to_string_lossy());
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(|path| PathBuf::from(path.as_ref()));
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+impl ConfigFile {
+    /// Reads and validates the file against the flags `app` knows. Settings `cli` already has are left out.
+    pub fn load(path: &Path, app: &App, cli: &[OsString]) -> Result<Self, Error> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(path, &text, app, cli)
+    }
+
+    fn parse(path: &Path, text: &str, app: &App, cli: &[OsString]) -> Result<Self, Error> {
+        let table: BTreeMap<String, Spanned<Value>> =
+            toml::from_str(text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut entries = Vec::new();
+        for (key, value) in table {
+            let line = text[..value.span().start].matches('\n').count() + 1;
+            let err = |e: String| format!("{}:{}: {}: {}", path.display(), line, key, e);
+            let arg = app
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(key.as_str()))
+                .filter(|arg| key != "config" && !arg.is_positional())
+                .ok_or_else(|| err("unknown setting".into()))?;
+            let args = to_args(arg, value.get_ref()).map_err(err)?;
+            validate(arg, &args).map_err(err)?;
+            if on_command_line(arg, cli) {
+                debug!("{}: {} is overridden by the command line", path.display(), key);
+                continue;
+            }
+            entries.push(Entry { key, line, args });
+        }
+        entries.sort_by_key(|entry| entry.line);
+        Ok(Self { path: path.to_path_buf(), entries })
+    }
+
+    /// `cli` with the settings from the file put in front, so the command line still gets the last word
+    pub fn args(&self, cli: &[OsString]) -> Vec<OsString> {
+        let file = self.entries.iter().flat_map(|entry| entry.args.iter().map(OsString::from));
+        cli.iter().take(1).cloned().chain(file).chain(cli.iter().skip(1).cloned()).collect()
+    }
+
+    /// Keys added, removed or changed in `newer`
+    fn changed<'a>(&'a self, newer: &'a Self) -> Vec<&'a str> {
+        let find = |file: &'a Self, key: &str| file.entries.iter().find(|entry| entry.key == key);
+        let mut keys = Vec::<&str>::new();
+        for entry in self.entries.iter().chain(&newer.entries) {
+            let (old, new) = (find(self, &entry.key), find(newer, &entry.key));
+            if old.map(|entry| &entry.args) != new.map(|entry| &entry.args) && !keys.contains(&entry.key.as_str()) {
+                keys.push(&entry.key);
+            }
+        }
+        keys
+    }
+}
+
+fn to_args(arg: &Arg, value: &Value) -> Result<Vec<String>, String> {
+    let flag = format!("--{}", arg.get_long().unwrap_or_default());
+    if !arg.is_takes_value_set() {
+        return match value {
+            Value::Boolean(true) => Ok(vec![flag]),
+            Value::Boolean(false) => Ok(vec![]),
+            _ => Err("expected true or false".into()),
+        };
+    }
+    let values = match value {
+        Value::Array(values) => values.iter().map(scalar).collect::<Result<Vec<_>, _>>()?,
+        value => vec![scalar(value)?],
+    };
+    match arg.get_value_delimiter() {
+        Some(delimiter) if arg.is_use_value_delimiter_set() => Ok(vec![flag, values.join(&delimiter.to_string())]),
+        _ => Ok(values.into_iter().flat_map(|value| [flag.clone(), value]).collect()),
+    }
+}
+
+fn scalar(value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(value) => Ok(value.clone()),
+        Value::Integer(value) => Ok(value.to_string()),
+        Value::Float(value) => Ok(value.to_string()),
+        Value::Boolean(value) => Ok(value.to_string()),
+        value => Err(format!("expected a string, number or boolean, got {}", value.type_str())),
+    }
+}
+
+/// Lets clap check the value on its own, so a mistake is reported with the line it is on
+fn validate(arg: &Arg, args: &[String]) -> Result<(), String> {
+    let app = App::new("config").arg(arg.clone().required(false));
+    match app.try_get_matches_from(std::iter::once("config").chain(args.iter().map(String::as_str))) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            let message = e.to_string();
+            let message = message.lines().next().unwrap_or_default();
+            Err(message.trim_start_matches("error: ").to_string())
+        }
+    }
+}
+
+fn on_command_line(arg: &Arg, cli: &[OsString]) -> bool {
+    let long = arg.get_long().map(|long| format!("--{}", long));
+    let short = arg.get_short();
+    cli.iter().skip(1).map(|arg| arg.to_string_lossy()).any(|arg| {
+        let long_match = long.as_ref().is_some_and(|long| {
+            arg == long.as_str() || arg.strip_prefix(long.as_str()).is_some_and(|rest| rest.starts_with('='))
+        });
+        let short_match = short.is_some_and(|short| {
+            !arg.starts_with("--") && arg.strip_prefix('-').is_some_and(|rest| rest.starts_with(short))
+        });
+        long_match || short_match
+    })
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Reloads the file on SIGHUP or when it changes, and sends the new settings to `reload`.
+/// Pools, payout and stratum settings are applied by reconnecting, the workers keep running. The others,
+/// such as the log level, are applied without touching the connection.
+pub async fn watch(mut config: ConfigFile, app: App<'static>, cli: Vec<OsString>, reload: Settings) {
+    let mut last_modified = modified(&config.path);
+    let mut hangup = Hangup::new();
+    let mut ticker = tokio::time::interval(WATCH_RATE);
+    loop {
+        let forced = tokio::select! {
+            _ = ticker.tick() => false,
+            _ = hangup.recv() => true,
+        };
+        let now_modified = modified(&config.path);
+        if !forced && now_modified == last_modified {
+            continue;
+        }
+        last_modified = now_modified;
+
+        let loaded = ConfigFile::load(&config.path, &app, &cli).and_then(|file| {
+            let matches = app.clone().try_get_matches_from(file.args(&cli))?;
+            let mut opt = Opt::from_arg_matches(&matches)?;
+            opt.process()?;
+            Ok((file, opt))
+        });
+        let (file, opt) = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                error!("Keeping the current settings, failed reloading {}", e);
+                continue;
+            }
+        };
+        let (reloadable, restart): (Vec<&str>, Vec<&str>) =
+            config.changed(&file).into_iter().partition(|key| reloadable(key));
+        if !restart.is_empty() {
+            warn!("Restart the miner to apply {} from {}", restart.join(", "), file.path.display());
+        }
+        if reloadable.is_empty() {
+            info!("Reloaded {}, nothing to apply", file.path.display());
+            config = file;
+            continue;
+        }
+        info!("Reloaded {}, applying {}", file.path.display(), reloadable.join(", "));
+        let reconnect = reloadable.iter().any(|key| reconnects(key));
+        config = file;
+        if reload.is_closed() {
+            return;
+        }
+        logger::set_level(opt.log_level());
+        reload.send_if_modified(|current| {
+            *current = Arc::new(opt);
+            reconnect
+        });
+    }
+}
+
+fn reloadable(key: &str) -> bool {
+    !STARTUP_ONLY.contains(&key) && Opt::into_app().get_arguments().any(|arg| arg.get_long() == Some(key))
+}
+
+fn reconnects(key: &str) -> bool {
+    key.starts_with("stratum-") || CONNECTION.contains(&key)
+}
+
+/// SIGHUP, where there is one
+struct Hangup {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangup {
+    fn new() -> Self {
+        Self {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok(),
+        }
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            if signal.recv().await.is_some() {
+                return;
+            }
+        }
+        std::future::pending::<()>().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{path, reconnects, ConfigFile};
+    use crate::cli::Opt;
+    use clap::{FromArgMatches, IntoApp};
+    use std::ffi::OsString;
+    use std::path::Path;
+
+    fn cli(args: &[&str]) -> Vec<OsString> {
+        std::iter::once("karlsen-miner").chain(args.iter().copied()).map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_config_file() {
+        let text = r#"
+mining-address = "karlsen:file"
+debug = true
+devfund-percent = "1.50"
+pool-split = ["stratum+tcp://a:5555=60", "stratum+tcp://b:5555=40"]
+"#;
+        let cli = cli(&["--config", "miner.toml", "-a", "karlsen:cli"]);
+        assert_eq!(path(&cli), Some("miner.toml".into()));
+        let app = Opt::into_app();
+        let file = ConfigFile::parse(Path::new("miner.toml"), text, &app, &cli).unwrap();
+        let opt = Opt::from_arg_matches(&app.try_get_matches_from(file.args(&cli)).unwrap()).unwrap();
+        assert_eq!(opt.mining_address, "karlsen:cli");
+        assert!(opt.debug);
+        assert_eq!(opt.devfund_percent, 150);
+        assert_eq!(opt.pool_split.len(), 2);
+
+        let changed = ConfigFile::parse(Path::new("miner.toml"), "debug = true\n", &Opt::into_app(), &cli).unwrap();
+        assert_eq!(file.changed(&changed), ["devfund-percent", "pool-split"]);
+        assert!(reconnects("pool-split") && reconnects("stratum-keepalive"));
+        assert!(!reconnects("debug") && !reconnects("watchdog-timeout"));
+    }
+
+    #[test]
+    fn test_config_errors() {
+        let app = Opt::into_app();
+        let err =
+            |text: &str| ConfigFile::parse(Path::new("miner.toml"), text, &app, &cli(&[])).unwrap_err().to_string();
+        assert!(err("debug = true\ndevfund-percent = \"1.234\"\n").starts_with("miner.toml:2: devfund-percent: "));
+        assert!(err("\n\nworkload = 3\n").starts_with("miner.toml:3: workload: unknown setting"));
+        assert!(err("debug = \"yes\"\n").starts_with("miner.toml:1: debug: expected true or false"));
+        assert!(err("mining-address = [\"a\", \"b\"]\n").starts_with("miner.toml:1: mining-address: "));
+        assert!(err("debug = \n").starts_with("miner.toml: TOML parse error at line 1"));
+    }
+}
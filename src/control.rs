@@ -0,0 +1,228 @@
+//! HTTP control API, enabled with `--control-bind`. Devices are paused, resumed and tuned in place,
+//! a new pool or payout address is applied by reconnecting. The workers keep their DAG either way.
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::Router;
+use log::info;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+use crate::api::json;
+use crate::cli::Opt;
+use crate::config::Settings;
+use crate::miner::Controls;
+use crate::Error;
+
+const SCHEMES: [&str; 3] = ["stratum+tcp://", "stratum+ssl://", "grpc://"];
+
+#[derive(Clone)]
+struct Control {
+    controls: Arc<Controls>,
+    settings: Settings,
+}
+
+/// Devices by full id or `#N`, none means all of them
+#[derive(Default, Deserialize)]
+struct DevicesRequest {
+    #[serde(default)]
+    devices: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct WorkloadRequest {
+    device: String,
+    workload: usize,
+}
+
+#[derive(Deserialize)]
+struct AddressRequest {
+    address: String,
+}
+
+#[derive(Serialize)]
+struct DevicesReply {
+    devices: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct WorkloadReply {
+    device: String,
+    workload: usize,
+}
+
+#[derive(Serialize)]
+struct AddressReply {
+    address: String,
+}
+
+#[derive(Serialize)]
+struct ErrorReply {
+    error: String,
+}
+
+pub async fn serve(bind: SocketAddr, controls: Arc<Controls>, settings: Settings) -> Result<(), Error> {
+    let listener = TcpListener::bind(bind).await?;
+    info!("Control API listening on http://{}", listener.local_addr()?);
+    axum::serve(listener, router(Control { controls, settings })).await?;
+    Ok(())
+}
+
+fn router(control: Control) -> Router {
+    Router::new()
+        .route(
+            "/pause",
+            post(|State(control): State<Control>, body: String| async move { reply(control.pause(&body, true)) }),
+        )
+        .route(
+            "/resume",
+            post(|State(control): State<Control>, body: String| async move { reply(control.pause(&body, false)) }),
+        )
+        .route(
+            "/workload",
+            post(|State(control): State<Control>, body: String| async move { reply(control.workload(&body)) }),
+        )
+        .route("/pool", post(|State(control): State<Control>, body: String| async move { reply(control.pool(&body)) }))
+        .route(
+            "/mining-address",
+            post(|State(control): State<Control>, body: String| async move { reply(control.mining_address(&body)) }),
+        )
+        .with_state(control)
+}
+
+fn reply<T: Serialize>(result: Result<T, Error>) -> Response {
+    match result {
+        Ok(value) => json(&value),
+        Err(e) => (StatusCode::BAD_REQUEST, json(&ErrorReply { error: e.to_string() })).into_response(),
+    }
+}
+
+/// An empty body stands for the request's defaults
+fn parse<T: DeserializeOwned + Default>(body: &str) -> Result<T, Error> {
+    match body.trim().is_empty() {
+        true => Ok(T::default()),
+        false => Ok(serde_json::from_str(body)?),
+    }
+}
+
+impl Control {
+    fn pause(&self, body: &str, paused: bool) -> Result<DevicesReply, Error> {
+        let request: DevicesRequest = parse(body)?;
+        Ok(DevicesReply { devices: self.controls.set_paused(&request.devices, paused)? })
+    }
+
+    fn workload(&self, body: &str) -> Result<WorkloadReply, Error> {
+        let request: WorkloadRequest = serde_json::from_str(body)?;
+        let device = self.controls.set_workload(&request.device, request.workload)?;
+        Ok(WorkloadReply { device, workload: request.workload })
+    }
+
+    fn pool(&self, body: &str) -> Result<AddressReply, Error> {
+        let request: AddressRequest = serde_json::from_str(body)?;
+        self.reconnect(|opt| {
+            opt.karlsend_address = request.address;
+            opt.pool_split.clear();
+        })
+        .map(|opt| AddressReply { address: opt.karlsend_address.clone() })
+    }
+
+    fn mining_address(&self, body: &str) -> Result<AddressReply, Error> {
+        let request: AddressRequest = serde_json::from_str(body)?;
+        // Another network's address would also switch off the devfund
+        let prefix = self.settings.borrow().address_prefix();
+        match request.address.split_once(':') {
+            Some((network, payload)) if network == prefix && !payload.is_empty() => {}
+            _ => return Err(format!("Not a {} address: {}", prefix, request.address).into()),
+        }
+        self.reconnect(|opt| opt.mining_address = request.address)
+            .map(|opt| AddressReply { address: opt.mining_address.clone() })
+    }
+
+    /// Applies `change` to the current settings, which makes the miner reconnect with them
+    fn reconnect(&self, change: impl FnOnce(&mut Opt)) -> Result<Arc<Opt>, Error> {
+        let mut opt = Opt::clone(&self.settings.borrow());
+        change(&mut opt);
+        opt.process()?;
+        if !SCHEMES.iter().any(|scheme| opt.karlsend_address.starts_with(scheme)) {
+            return Err(format!("Did not recognize pool/grpc address schema: {}", opt.karlsend_address).into());
+        }
+        let opt = Arc::new(opt);
+        info!("Reconnecting to {} mining for {} on request", opt.karlsend_address, opt.mining_address);
+        self.settings.send(opt.clone()).map_err(|_| "Miner is shutting down")?;
+        Ok(opt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{router, Control};
+    use crate::cli::Opt;
+    use crate::miner::MinerManager;
+    use crate::stats::Statistics;
+    use clap::Parser;
+    use karlsen_miner::PluginManager;
+    use serde_json::Value;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::{mpsc, watch};
+
+    async fn post(address: std::net::SocketAddr, path: &str, body: &str) -> (String, Value) {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            path,
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), serde_json::from_str(body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_control() {
+        let mut opt = Opt::parse_from(["karlsen-miner", "-a", "karlsen:miner", "--devfund-percent", "1"]);
+        opt.process().unwrap();
+        let (reload, mut settings) = watch::channel(Arc::new(opt));
+        let miner_manager =
+            MinerManager::new(mpsc::channel(1).0, &PluginManager::new(), Arc::new(Statistics::default()));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let control = Control { controls: miner_manager.controls(), settings: Arc::new(reload) };
+        tokio::spawn(async move { axum::serve(listener, router(control)).await });
+
+        let (status, reply) = post(address, "/pause", "").await;
+        assert!(status.contains("200"), "{}", status);
+        assert_eq!(reply["devices"], Value::Array(vec![]));
+        let (status, reply) = post(address, "/resume", r##"{"devices": ["#3"]}"##).await;
+        assert!(status.contains("400"), "{}", status);
+        assert_eq!(reply["error"], "Unknown device #3");
+
+        settings.borrow_and_update();
+        let (_, reply) = post(address, "/pool", r#"{"address": "10.0.0.1"}"#).await;
+        assert_eq!(reply["address"], "grpc://10.0.0.1:42110");
+        assert!(settings.has_changed().unwrap());
+        assert_eq!(settings.borrow_and_update().karlsend_address, "grpc://10.0.0.1:42110");
+        let (_, reply) = post(address, "/mining-address", r#"{"address": "karlsen:other"}"#).await;
+        assert_eq!(reply["address"], "karlsen:other");
+        assert_eq!(settings.borrow_and_update().devfund_percent, 100);
+        assert_eq!(settings.borrow().karlsend_address, "grpc://10.0.0.1:42110");
+        for other in ["foo:x", "karlsentest:other", "karlsen:", "karlsen"] {
+            let (status, reply) = post(address, "/mining-address", &format!(r#"{{"address": "{}"}}"#, other)).await;
+            assert!(status.contains("400"), "{}", status);
+            assert_eq!(reply["error"], format!("Not a karlsen address: {}", other));
+        }
+        assert!(!settings.has_changed().unwrap());
+        let (status, _) = post(address, "/pool", r#"{"address": "http://pool:80"}"#).await;
+        assert!(status.contains("400"), "{}", status);
+    }
+}
@@ -118,7 +118,17 @@ pub trait Worker {
     fn sync(&self) -> Result<(), Error>;
 
     fn get_workload(&self) -> usize;
+    /// Changes the number of nonces tried per kernel run, without rebuilding the worker
+    fn set_workload(&mut self, _workload: usize) -> Result<(), Error> {
+        Err("Changing the workload is not supported by this worker".into())
+    }
     fn copy_output_to(&mut self, nonces: &mut Vec<u64>) -> Result<(), Error>;
+
+    /// Device readings such as temperature or power draw, as `(name, value)`.
+    /// Names should be snake_case with a unit suffix, e.g. `temperature_celsius`, they end up in metric names.
+    fn telemetry(&self) -> Vec<(&'static str, f64)> {
+        Vec::new()
+    }
 }
 
 pub fn load_plugins<'help>(
@@ -0,0 +1,82 @@
+//! Logging through env_logger, with the level switchable at runtime and the records optionally kept in memory
+//! instead of written out, while the dashboard owns the terminal.
+
+use log::{LevelFilter, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use time::macros::format_description;
+use time::OffsetDateTime;
+
+use crate::Error;
+
+const RECENT_RECORDS: usize = 200;
+
+static DEBUG: AtomicBool = AtomicBool::new(false);
+static CAPTURE: AtomicBool = AtomicBool::new(false);
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Logs through one of two env_loggers, so a reload can switch `debug` on and off.
+/// `RUST_LOG` still applies on top of either.
+struct Logger {
+    info: env_logger::Logger,
+    debug: env_logger::Logger,
+}
+
+impl Logger {
+    fn current(&self) -> &env_logger::Logger {
+        match DEBUG.load(Ordering::Relaxed) {
+            true => &self.debug,
+            false => &self.info,
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.current().enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !CAPTURE.load(Ordering::Relaxed) {
+            return self.current().log(record);
+        }
+        if self.current().matches(record) {
+            let time = OffsetDateTime::now_utc().format(format_description!("[hour]:[minute]:[second]"));
+            let line = format!("{} {:<5} {}", time.unwrap_or_default(), record.level(), record.args());
+            let mut recent = RECENT.lock().unwrap();
+            if recent.len() == RECENT_RECORDS {
+                recent.pop_front();
+            }
+            recent.push_back(line);
+        }
+    }
+
+    fn flush(&self) {
+        self.current().flush()
+    }
+}
+
+pub fn init(level: LevelFilter) -> Result<(), Error> {
+    let build = |level| env_logger::builder().filter_level(level).parse_default_env().build();
+    let logger = Logger { info: build(LevelFilter::Info), debug: build(LevelFilter::Debug) };
+    log::set_max_level(logger.info.filter().max(logger.debug.filter()));
+    log::set_boxed_logger(Box::new(logger))?;
+    set_level(level);
+    Ok(())
+}
+
+pub fn set_level(level: LevelFilter) {
+    DEBUG.store(level >= LevelFilter::Debug, Ordering::Relaxed);
+}
+
+/// Keeps records in memory instead of writing them, see `recent`
+pub fn capture(enabled: bool) {
+    CAPTURE.store(enabled, Ordering::Relaxed);
+}
+
+/// The last `count` records kept while capturing, oldest first
+pub fn recent(count: usize) -> Vec<String> {
+    let recent = RECENT.lock().unwrap();
+    recent.iter().skip(recent.len().saturating_sub(count)).cloned().collect()
+}
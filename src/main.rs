@@ -3,32 +3,51 @@
 use std::env::consts::DLL_EXTENSION;
 use std::env::current_exe;
 use std::error::Error as StdError;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 
 use clap::{App, FromArgMatches, IntoApp};
+use futures::future;
 use karlsen_miner::PluginManager;
-use log::{error, info};
+use log::{error, info, warn, LevelFilter};
 use rand::{rng, RngCore};
 use std::fs;
+use std::path::Path;
 use std::sync::atomic::AtomicU16;
 use std::sync::Arc;
-use std::thread::sleep;
 use std::time::Duration;
+use tokio::sync::mpsc;
 
+use crate::benchmark::BenchmarkOpt;
 use crate::cli::Opt;
 use crate::client::grpc::KarlsendHandler;
-use crate::client::stratum::StratumHandler;
-use crate::client::Client;
+use crate::client::proxy::Proxy;
+use crate::client::split::{PoolSplit, SessionSink};
+use crate::client::stratum::{recording, StratumHandler, StratumOptions};
+use crate::client::{Client, Reconnect};
+use crate::config::ConfigFile;
 use crate::miner::MinerManager;
+use crate::pow::BlockSeed;
+use crate::stats::Statistics;
 use crate::target::Uint256;
 
+mod api;
+mod benchmark;
 mod cli;
 mod client;
+mod config;
+mod control;
 mod karlsend_messages;
+mod logger;
+mod metrics;
 mod miner;
 mod pow;
+mod schedule;
+mod shutdown;
+mod stats;
 mod target;
+mod tui;
 mod watch;
+mod watchdog;
 
 //remove the opencl plugin support for the moment
 const WHITELIST: [&str; 2] = ["libkarlsencuda", "karlsencuda"];
@@ -73,6 +92,9 @@ async fn get_client(
     mining_address: String,
     mine_when_not_synced: bool,
     block_template_ctr: Arc<AtomicU16>,
+    stratum_options: StratumOptions,
+    proxy: Option<Proxy>,
+    statistics: &Arc<Statistics>,
 ) -> Result<Box<dyn Client + 'static>, Error> {
     if karlsend_address.starts_with("stratum+tcp://") {
         let (_schema, address) = karlsend_address.split_once("://").unwrap();
@@ -82,6 +104,9 @@ async fn get_client(
             mine_when_not_synced,
             Some(block_template_ctr.clone()),
             false, // TCP
+            stratum_options,
+            proxy,
+            statistics,
         )
         .await?)
     } else if karlsend_address.starts_with("stratum+ssl://") {
@@ -92,6 +117,9 @@ async fn get_client(
             mine_when_not_synced,
             Some(block_template_ctr.clone()),
             true, // SSL
+            stratum_options,
+            proxy,
+            statistics,
         )
         .await?)
     } else if karlsend_address.starts_with("grpc://") {
@@ -100,6 +128,8 @@ async fn get_client(
             mining_address.clone(),
             mine_when_not_synced,
             Some(block_template_ctr.clone()),
+            proxy,
+            statistics.clone(),
         )
         .await?)
     } else {
@@ -107,16 +137,20 @@ async fn get_client(
     }
 }
 
-async fn client_main(
+async fn connect_client(
     opt: &Opt,
+    address: &str,
     block_template_ctr: Arc<AtomicU16>,
-    plugin_manager: &PluginManager,
-) -> Result<(), Error> {
+    statistics: &Arc<Statistics>,
+) -> Result<Box<dyn Client + 'static>, Error> {
     let mut client = get_client(
-        opt.karlsend_address.clone(),
+        address.to_string(),
         opt.mining_address.clone(),
         opt.mine_when_not_synced,
-        block_template_ctr.clone(),
+        block_template_ctr,
+        opt.stratum_options(),
+        opt.proxy.clone(),
+        statistics,
     )
     .await?;
 
@@ -124,10 +158,130 @@ async fn client_main(
         client.add_devfund(opt.devfund_address.clone(), opt.devfund_percent);
     }
     client.register().await?;
-    let mut miner_manager = MinerManager::new(client.get_block_channel(), plugin_manager);
-    client.listen(&mut miner_manager).await?;
+    Ok(client)
+}
+
+async fn client_main(
+    opt: &Opt,
+    address: &str,
+    block_template_ctr: Arc<AtomicU16>,
+    miner_manager: &mut MinerManager,
+    shares: &mut mpsc::Receiver<BlockSeed>,
+    statistics: &Arc<Statistics>,
+) -> Result<Option<Reconnect>, Error> {
+    let mut client = connect_client(opt, address, block_template_ctr, statistics).await?;
+    let _connection = statistics.connected(address);
+    let block_channel = client.get_block_channel();
+    let result = tokio::select! {
+        result = client.listen(miner_manager) => result,
+        _ = forward_shares(shares, block_channel) => Err("Miner closed the share channel".into()),
+    };
+    miner_manager.idle();
+    result?;
+    Ok(client.reconnect_request())
+}
+
+/// Hands what the workers find to the connected client. The workers outlive the clients.
+async fn forward_shares(shares: &mut mpsc::Receiver<BlockSeed>, block_channel: mpsc::Sender<BlockSeed>) {
+    while let Some(share) = shares.recv().await {
+        if block_channel.send(share).await.is_err() {
+            warn!("Dropping share, the client is closing");
+        }
+    }
+}
+
+/// Where and when to connect again after a client closed. A bad pool redirect falls back to `configured`.
+fn next_connection(configured: &str, address: String, result: Result<Option<Reconnect>, Error>) -> Reconnect {
+    let address = match result {
+        Ok(Some(reconnect)) => {
+            info!("Reconnecting to {} in {} seconds...", reconnect.address, reconnect.wait.as_secs());
+            return reconnect;
+        }
+        Ok(None) => {
+            info!("Client closed gracefully");
+            address
+        }
+        Err(e) => {
+            error!("Client closed with error: {:?}", e);
+            configured.to_string()
+        }
+    };
+    info!("Client closed, reconnecting in 5 seconds...");
+    Reconnect { address, wait: Duration::from_secs(5) }
+}
+
+/// Keeps every pool of `--pool-split` connected and lets the split decide whose jobs get mined
+async fn split_main(
+    opt: &Opt,
+    miner_manager: &mut MinerManager,
+    shares: &mut mpsc::Receiver<BlockSeed>,
+    statistics: &Arc<Statistics>,
+) -> Result<(), Error> {
+    let (split, sinks) = PoolSplit::new(&opt.pool_split);
+    let sessions =
+        opt.pool_split.iter().zip(sinks).map(|(pool, sink)| split_session(opt, &pool.address, sink, statistics));
+    tokio::select! {
+        _ = future::join_all(sessions) => Ok(()),
+        result = split.run(miner_manager, shares) => result,
+    }
+}
+
+async fn split_session(opt: &Opt, pool: &str, mut sink: SessionSink, statistics: &Arc<Statistics>) {
+    let mut address = pool.to_string();
+    let block_template_ctr = sink.block_template_ctr();
+    loop {
+        let result = async {
+            let mut client = connect_client(opt, &address, block_template_ctr.clone(), statistics).await?;
+            // Jobs of a pool off the schedule don't count towards the devfund, the split counts the mined ones
+            client.count_jobs(false);
+            let _connection = statistics.connected(&address);
+            sink.connected(client.get_block_channel());
+            client.listen(&mut sink).await?;
+            Ok(client.reconnect_request())
+        }
+        .await;
+        sink.disconnected();
+        let reconnect = next_connection(pool, address, result);
+        address = reconnect.address;
+        tokio::time::sleep(reconnect.wait).await;
+    }
+}
+
+async fn replay_main(opt: &Opt, path: &Path) -> Result<(), Error> {
+    info!("Replaying stratum recording {}", path.display());
+    let statistics = Arc::new(Statistics::default());
+    let (mut client, report) =
+        StratumHandler::replay(recording::load(path)?, opt.mining_address.clone(), opt.stratum_options(), &statistics);
+    client.register().await?;
+    // No workers are needed, the recorded shares are fed back as if they were found
+    let mut miner_manager = MinerManager::new(client.get_block_channel(), &PluginManager::new(), statistics.clone());
+    miner_manager.discard_jobs();
+    if let Err(e) = client.listen(&mut miner_manager).await {
+        info!("Replay ended: {}", e);
+    }
     drop(miner_manager);
-    Ok(())
+    drop(client);
+    let report = report.await?;
+    info!("Shares: {}", statistics.snapshot().lifetime);
+    report.divergences.iter().for_each(|divergence| warn!("{}", divergence));
+    match report.divergences.len() {
+        0 => Ok(()),
+        n => Err(format!("Replay diverged from the recording {} times", n).into()),
+    }
+}
+
+/// `karlsen-miner benchmark [OPTIONS]`, which takes the plugin options as well
+async fn benchmark_main(plugins: &[String], cli: &[OsString]) -> Result<(), Error> {
+    let (app, mut plugin_manager) = karlsen_miner::load_plugins(BenchmarkOpt::into_app(), plugins)?;
+    let matches = app.get_matches_from(cli.iter().take(1).chain(&cli[2..]));
+    let worker_count = plugin_manager.process_options(&matches)?;
+    let opt = BenchmarkOpt::from_arg_matches(&matches)?;
+    logger::init(if opt.debug { LevelFilter::Debug } else { LevelFilter::Info })?;
+    info!("karlsen-miner GPU {} benchmark, plugins found {} workers", env!("CARGO_PKG_VERSION"), worker_count);
+    if worker_count == 0 {
+        return Err("No GPU workers specified".into());
+    }
+    benchmark::main(&opt, &plugin_manager).await
 }
 
 #[tokio::main]
@@ -139,20 +293,28 @@ async fn main() -> Result<(), Error> {
     let mut path = current_exe().unwrap_or_default();
     path.pop(); // Getting the parent directory
     let plugins = filter_plugins(path.to_str().unwrap_or("."));
+    let cli = std::env::args_os().collect::<Vec<OsString>>();
+    if cli.get(1).is_some_and(|arg| arg == "benchmark") {
+        return benchmark_main(&plugins, &cli).await;
+    }
     let (app, mut plugin_manager): (App, PluginManager) = karlsen_miner::load_plugins(Opt::into_app(), &plugins)?;
 
-    let matches = app.get_matches();
+    let config = config::path(&cli).map(|path| ConfigFile::load(&path, &app, &cli)).transpose()?;
+    let matches = app.clone().get_matches_from(config.as_ref().map_or_else(|| cli.clone(), |config| config.args(&cli)));
 
     let worker_count = plugin_manager.process_options(&matches)?;
     let mut opt: Opt = Opt::from_arg_matches(&matches)?;
     opt.process()?;
-    env_logger::builder().filter_level(opt.log_level()).parse_default_env().init();
+    logger::init(opt.log_level())?;
     info!("=================================================================================");
     info!("                 karlsen-miner GPU {}", env!("CARGO_PKG_VERSION"));
     info!(" Mining for: {}", opt.mining_address);
     info!("=================================================================================");
     info!("Found plugins: {:?}", plugins);
     info!("GPU plugins found {} workers", worker_count);
+    if let Some(ref path) = opt.stratum_replay {
+        return replay_main(&opt, path).await;
+    }
     if worker_count == 0 {
         error!("No GPU workers specified");
         return Err("No GPU workers specified".into());
@@ -167,12 +329,80 @@ async fn main() -> Result<(), Error> {
             opt.devfund_address
         );
     }
+    let statistics = Arc::new(Statistics::default());
+    if let Some(bind) = opt.api_bind {
+        let statistics = statistics.clone();
+        tokio::spawn(async move {
+            if let Err(e) = api::serve(bind, statistics).await {
+                error!("API server stopped: {}", e);
+            }
+        });
+    }
+    if opt.tui {
+        tui::spawn(statistics.clone());
+    }
+    let (share_channel, mut shares) = mpsc::channel(1);
+    let mut miner_manager = MinerManager::new(share_channel, &plugin_manager, statistics.clone());
+    let (reload, mut settings) = tokio::sync::watch::channel(Arc::new(opt));
+    let reload = Arc::new(reload);
+    if let Some(bind) = settings.borrow().control_bind {
+        let (controls, reload) = (miner_manager.controls(), reload.clone());
+        tokio::spawn(async move {
+            if let Err(e) = control::serve(bind, controls, reload).await {
+                error!("Control API stopped: {}", e);
+            }
+        });
+    }
+    tokio::spawn(schedule::run(miner_manager.controls(), reload.clone()));
+    tokio::spawn(watchdog::run(statistics.clone(), miner_manager.controls(), reload.clone()));
+    if let Some(config) = config {
+        tokio::spawn(config::watch(config, app, cli, reload));
+    }
+    let controls = miner_manager.controls();
     loop {
-        match client_main(&opt, block_template_ctr.clone(), &plugin_manager).await {
-            Ok(_) => info!("Client closed gracefully"),
-            Err(e) => error!("Client closed with error: {:?}", e),
+        let opt = settings.borrow_and_update().clone();
+        logger::set_level(opt.log_level());
+        {
+            let mining = mine(&opt, block_template_ctr.clone(), &mut miner_manager, &mut shares, &statistics);
+            tokio::pin!(mining);
+            tokio::select! {
+                result = &mut mining => return result,
+                Ok(()) = settings.changed() => info!("Settings changed, reconnecting"),
+                _ = shutdown::requested() => {
+                    // The client keeps running until the last shares are answered
+                    tokio::select! {
+                        result = &mut mining => return result,
+                        _ = shutdown::drain(&controls, &statistics) => break,
+                    }
+                }
+            }
         }
-        info!("Client closed, reconnecting in 5 seconds...");
-        sleep(Duration::from_secs(5));
+        miner_manager.idle();
+    }
+    drop(miner_manager);
+    shutdown::log_summary(&statistics);
+    match shutdown::exit_code() {
+        0 => Ok(()),
+        code => std::process::exit(code),
+    }
+}
+
+/// Mines with `opt` until an unrecoverable error, reconnecting whenever the client closes
+async fn mine(
+    opt: &Opt,
+    block_template_ctr: Arc<AtomicU16>,
+    miner_manager: &mut MinerManager,
+    shares: &mut mpsc::Receiver<BlockSeed>,
+    statistics: &Arc<Statistics>,
+) -> Result<(), Error> {
+    if !opt.pool_split.is_empty() {
+        return split_main(opt, miner_manager, shares, statistics).await;
+    }
+    let mut address = opt.karlsend_address.clone();
+    loop {
+        let result = client_main(opt, &address, block_template_ctr.clone(), miner_manager, shares, statistics).await;
+        let reconnect = next_connection(&opt.karlsend_address, address, result);
+        address = reconnect.address;
+        tokio::time::sleep(reconnect.wait).await;
     }
 }
@@ -0,0 +1,222 @@
+//! Prometheus text exposition of the miner's statistics, served at `/metrics`.
+//! Metric names and labels are part of the interface, dashboards and alerts depend on them.
+
+use std::fmt::Write;
+
+use crate::stats::Statistics;
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+struct Metric {
+    name: String,
+    kind: &'static str,
+    help: &'static str,
+    samples: Vec<(Vec<(&'static str, String)>, f64)>,
+}
+
+impl Metric {
+    fn new(name: &str, kind: &'static str, help: &'static str) -> Self {
+        Self { name: format!("karlsen_miner_{}", name), kind, help, samples: Vec::new() }
+    }
+
+    fn sample(mut self, labels: Vec<(&'static str, String)>, value: f64) -> Self {
+        self.samples.push((labels, value));
+        self
+    }
+
+    fn value(self, value: f64) -> Self {
+        self.sample(Vec::new(), value)
+    }
+
+    fn write(&self, out: &mut String) {
+        _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        _ = writeln!(out, "# TYPE {} {}", self.name, self.kind);
+        for (labels, value) in &self.samples {
+            let labels = labels
+                .iter()
+                .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+                .collect::<Vec<_>>()
+                .join(",");
+            if labels.is_empty() {
+                _ = writeln!(out, "{} {}", self.name, value);
+            } else {
+                _ = writeln!(out, "{}{{{}}} {}", self.name, labels, value);
+            }
+        }
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Plugins name their readings, anything outside `[a-z0-9_]` is replaced so the metric name stays valid
+fn sanitize(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' }).collect()
+}
+
+/// HELP text of a device reading, the readings plugins commonly report are described
+fn reading_help(name: &str) -> &'static str {
+    match name {
+        "temperature_celsius" => "Temperature of the device in degrees Celsius",
+        "power_watts" => "Power the device draws in watts",
+        "fan_speed_percent" => "Fan speed of the device in percent of its maximum",
+        "core_clock_mhz" => "Core clock of the device in MHz",
+        "memory_clock_mhz" => "Memory clock of the device in MHz",
+        _ => "Device reading provided by the plugin",
+    }
+}
+
+pub fn render(statistics: &Statistics) -> String {
+    let summary = statistics.summary();
+    let devices = statistics.devices();
+    let shares = statistics.snapshot();
+    let blocks = statistics.blocks();
+    let job = statistics.job();
+
+    let mut metrics = vec![
+        Metric::new("info", "gauge", "Miner version").sample(vec![("version", summary.version.to_string())], 1.0),
+        Metric::new("uptime_seconds", "gauge", "Seconds since the miner started").value(summary.uptime_secs as f64),
+    ];
+
+    let mut hashes = Metric::new("device_hashes_total", "counter", "Hashes tried per device");
+    let mut hashrate = Metric::new("device_hashrate", "gauge", "Hashes per second per device over the last interval");
+    for device in &devices {
+        hashes = hashes.sample(vec![("device", device.id.clone())], device.hashes as f64);
+        hashrate = hashrate.sample(vec![("device", device.id.clone())], device.hashrate);
+    }
+    let mut restarts = Metric::new("device_restarts_total", "counter", "Times a device's worker was restarted");
+    for device in &devices {
+        restarts = restarts.sample(vec![("device", device.id.clone())], device.restarts as f64);
+    }
+    let mut averages = Metric::new("device_hashrate_average", "gauge", "Hashes per second per device by window");
+    let mut peaks = Metric::new("device_hashrate_peak", "gauge", "Highest 10s average per device");
+    let mut lows = Metric::new("device_hashrate_min", "gauge", "Lowest 10s average per device while hashing");
+    for device in &devices {
+        let rates = &device.hashrates;
+        for window in &rates.windows {
+            averages =
+                averages.sample(vec![("device", device.id.clone()), ("window", window.window.into())], window.hashrate);
+        }
+        averages = averages.sample(vec![("device", device.id.clone()), ("window", "session".into())], rates.session);
+        peaks = peaks.sample(vec![("device", device.id.clone())], rates.peak);
+        lows = lows.sample(vec![("device", device.id.clone())], rates.min);
+    }
+    metrics.extend([hashes, hashrate, restarts, averages, peaks, lows]);
+
+    // One gauge per reading, e.g. karlsen_miner_device_temperature_celsius
+    let mut readings = Vec::<Metric>::new();
+    for device in &devices {
+        for (name, value) in &device.telemetry {
+            let metric = format!("device_{}", sanitize(name));
+            let position = match readings.iter().position(|known| known.name == format!("karlsen_miner_{}", metric)) {
+                Some(position) => position,
+                None => {
+                    readings.push(Metric::new(&metric, "gauge", reading_help(name)));
+                    readings.len() - 1
+                }
+            };
+            readings[position].samples.push((vec![("device", device.id.clone())], *value));
+        }
+    }
+    metrics.extend(readings);
+
+    let mut average = Metric::new("hashrate_average", "gauge", "Hashes per second of all devices by window");
+    for window in &summary.hashrates.windows {
+        average = average.sample(vec![("window", window.window.to_string())], window.hashrate);
+    }
+    metrics.push(average.sample(vec![("window", "session".into())], summary.hashrates.session));
+
+    let mut effective = Metric::new("effective_hashrate", "gauge", "Hashes per second proven by accepted shares");
+    for window in &shares.hashrate {
+        effective = effective.sample(vec![("window", window.window.to_string())], window.effective);
+    }
+    metrics.push(effective);
+
+    let mut share_counts = Metric::new("shares_total", "counter", "Shares per pool by result");
+    for pool in &shares.pools {
+        let counts = pool.shares;
+        for (result, count) in [
+            ("accepted", counts.accepted),
+            ("stale", counts.stale),
+            ("low_difficulty", counts.low_difficulty),
+            ("duplicate", counts.duplicate),
+            ("lost", counts.lost),
+            ("suppressed", counts.suppressed),
+            ("dropped_duplicate", counts.dropped_duplicate),
+        ] {
+            share_counts =
+                share_counts.sample(vec![("pool", pool.pool.clone()), ("result", result.into())], count as f64);
+        }
+    }
+    metrics.push(share_counts);
+
+    metrics.extend([
+        Metric::new("blocks_found_total", "counter", "Blocks found by the workers").value(blocks.found as f64),
+        Metric::new("blocks_submitted_total", "counter", "Blocks submitted to the node").value(blocks.submitted as f64),
+        Metric::new("blocks_rejected_total", "counter", "Blocks the node rejected").value(blocks.rejected as f64),
+        Metric::new(
+            "blocks_dropped_duplicate_total",
+            "counter",
+            "Blocks found again for the same template and not sent",
+        )
+        .value(blocks.dropped_duplicate as f64),
+        Metric::new("jobs_total", "counter", "Jobs handed to the workers").value(job.jobs as f64),
+    ]);
+    if let Some(current) = job.current {
+        metrics.push(
+            Metric::new("last_job_age_seconds", "gauge", "Seconds since the workers got a new job")
+                .value(current.age_secs),
+        );
+    }
+
+    let mut connected = Metric::new("connected", "gauge", "Pools and nodes currently connected to");
+    for address in &summary.connections {
+        connected = connected.sample(vec![("pool", address.clone())], 1.0);
+    }
+    let mut reconnects = Metric::new("reconnects_total", "counter", "Times a pool or node was connected to again");
+    for (address, count) in statistics.reconnects() {
+        reconnects = reconnects.sample(vec![("pool", address)], count as f64);
+    }
+    metrics.extend([connected, reconnects]);
+
+    let mut out = String::new();
+    metrics.iter().for_each(|metric| metric.write(&mut out));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+    use crate::stats::{ShareEvent, Statistics};
+    use std::time::Duration;
+
+    #[test]
+    fn test_render() {
+        let statistics = Statistics::default();
+        statistics.device_hashes("#0 (Test \"GPU\")", 3000, Duration::from_secs(3));
+        statistics.device_telemetry("#0 (Test \"GPU\")", vec![("temperature_celsius", 64.0)]);
+        statistics.sample_hashes(&[("#0 (Test \"GPU\")".into(), 3000)], Duration::from_secs(3));
+        // Stratum sessions are started with host:port, connections with the URL the miner was given
+        statistics.start_session("pool:5555").record(ShareEvent::Accepted);
+        statistics.block_found("ab".into());
+        drop(statistics.connected("stratum+tcp://pool:5555"));
+        let _connection = statistics.connected("stratum+tcp://pool:5555");
+
+        let metrics = render(&statistics);
+        assert!(metrics.contains("# TYPE karlsen_miner_device_hashes_total counter\n"));
+        assert!(metrics.contains("karlsen_miner_device_hashes_total{device=\"#0 (Test \\\"GPU\\\")\"} 3000\n"));
+        assert!(metrics.contains("karlsen_miner_device_temperature_celsius{device=\"#0 (Test \\\"GPU\\\")\"} 64\n"));
+        assert!(metrics.contains("# HELP karlsen_miner_device_temperature_celsius Temperature of the device in "));
+        assert!(metrics.contains("karlsen_miner_hashrate_average{window=\"session\"} "));
+        assert!(
+            metrics.contains("karlsen_miner_device_hashrate_average{device=\"#0 (Test \\\"GPU\\\")\",window=\"10s\"} ")
+        );
+        assert!(metrics.contains("karlsen_miner_shares_total{pool=\"pool:5555\",result=\"accepted\"} 1\n"));
+        assert!(metrics.contains("karlsen_miner_shares_total{pool=\"pool:5555\",result=\"stale\"} 0\n"));
+        assert!(metrics.contains("karlsen_miner_blocks_found_total 1\n"));
+        assert!(metrics.contains("karlsen_miner_connected{pool=\"pool:5555\"} 1\n"));
+        assert!(metrics.contains("karlsen_miner_reconnects_total{pool=\"pool:5555\"} 1\n"));
+        assert!(!metrics.contains("last_job_age_seconds"));
+    }
+}
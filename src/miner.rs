@@ -2,9 +2,12 @@ use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use crate::pow::NoncePartition;
+use crate::stats::{Hashrates, Statistics};
 use crate::{pow, watch, Error};
+use async_trait::async_trait;
 use log::{error, info, warn};
 use tokio::sync::mpsc::Sender;
 use tokio::task::{self, JoinHandle};
@@ -15,6 +18,12 @@ use karlsen_miner::{PluginManager, WorkerSpec};
 
 type MinerHandler = std::thread::JoinHandle<Result<(), Error>>;
 
+/// Takes the jobs a client receives from its pool or node. `None` means there is nothing to mine.
+#[async_trait(?Send)]
+pub trait JobSink {
+    async fn process_block(&mut self, block: Option<BlockSeed>) -> Result<(), Error>;
+}
+
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 extern "C" fn signal_panic(_signal: nix::libc::c_int) {
     panic!("Forced shutdown");
@@ -80,33 +89,269 @@ fn register_freeze_handler() {
     warn!("Freeze handler is not implemented. Frozen threads are ignored");
 }
 
+/// Runtime settings of one device, picked up by its worker thread between kernel runs
+#[derive(Default)]
+pub struct DeviceControl {
+    paused: AtomicBool,
+    workload: Mutex<Option<usize>>,
+    /// Asks the supervisor to start the worker again
+    restart: AtomicBool,
+    health: WorkerHealth,
+}
+
+/// Pauses, resumes and tunes the devices of a `MinerManager` while it keeps running
+pub struct Controls {
+    devices: Vec<(String, Arc<DeviceControl>)>,
+    jobs: Arc<JobGate>,
+    statistics: Arc<Statistics>,
+}
+
+impl Controls {
+    /// Devices matching `selectors` by full id or by the `#N` prefix, or all of them when empty
+    fn select(&self, selectors: &[String]) -> Result<Vec<&(String, Arc<DeviceControl>)>, Error> {
+        if selectors.is_empty() {
+            return Ok(self.devices.iter().collect());
+        }
+        selectors
+            .iter()
+            .map(|selector| {
+                self.devices
+                    .iter()
+                    .find(|(id, _)| id == selector || id.split(' ').next() == Some(selector.as_str()))
+                    .ok_or_else(|| format!("Unknown device {}", selector).into())
+            })
+            .collect()
+    }
+
+    /// Returns the ids of the devices paused or resumed
+    pub fn set_paused(&self, selectors: &[String], paused: bool) -> Result<Vec<String>, Error> {
+        let devices = self.select(selectors)?;
+        for (id, control) in &devices {
+            if control.paused.swap(paused, Ordering::AcqRel) != paused {
+                info!("{} {}", if paused { "Pausing" } else { "Resuming" }, id);
+            }
+            self.statistics.device_paused(id, paused);
+        }
+        Ok(devices.into_iter().map(|(id, _)| id.clone()).collect())
+    }
+
+    /// Keeps the jobs from all workers while there is a `reason` to, they resume with the latest job
+    pub fn hold(&self, reason: Option<&str>) {
+        self.statistics.held(reason);
+        self.jobs.hold(reason.is_some());
+    }
+
+    /// Pauses every device for good, the workers finish their current kernel run
+    pub fn stop(&self) {
+        for (id, control) in &self.devices {
+            control.paused.store(true, Ordering::Release);
+            self.statistics.device_paused(id, true);
+        }
+    }
+
+    /// Whether any worker is in the middle of a kernel run
+    pub fn busy(&self) -> bool {
+        self.devices.iter().any(|(_, control)| control.health.busy_since.lock().unwrap().is_some())
+    }
+
+    /// Starts the workers of the devices matching `selectors` again, returns their ids
+    pub fn restart(&self, selectors: &[String]) -> Result<Vec<String>, Error> {
+        let devices = self.select(selectors)?;
+        devices.iter().for_each(|(_, control)| control.restart.store(true, Ordering::Release));
+        Ok(devices.into_iter().map(|(id, _)| id.clone()).collect())
+    }
+
+    /// Asks the worker to switch to `workload` nonces per kernel run, the next time it gets to it
+    pub fn set_workload(&self, selector: &str, workload: usize) -> Result<String, Error> {
+        if workload == 0 {
+            return Err("Workload must be above 0".into());
+        }
+        let (id, control) = self.select(&[selector.to_string()])?[0];
+        *control.workload.lock().unwrap() = Some(workload);
+        Ok(id.clone())
+    }
+}
+
+/// Progress of a worker thread, for the supervisor to tell a stuck kernel from an idle device
+#[derive(Default)]
+struct WorkerHealth {
+    /// Bumped when the supervisor gives up on a thread, a stuck thread that comes back then quits
+    generation: AtomicU64,
+    /// When the kernel run in progress started
+    busy_since: Mutex<Option<Instant>>,
+}
+
+impl WorkerHealth {
+    fn busy(&self, busy: bool) {
+        *self.busy_since.lock().unwrap() = busy.then(Instant::now);
+    }
+
+    fn stalled(&self) -> bool {
+        self.busy_since.lock().unwrap().is_some_and(|since| since.elapsed() >= STALL_TIMEOUT)
+    }
+}
+
+/// Everything a worker thread is started with, kept to start it again
+#[derive(Clone)]
+struct WorkerLaunch {
+    spec: Arc<dyn WorkerSpec>,
+    send_channel: Sender<BlockSeed>,
+    block_channel: watch::Receiver<Option<WorkerCommand>>,
+    hashes_tried: Arc<AtomicU64>,
+    worker_hashes_tried: Arc<AtomicU64>,
+    partition: NoncePartition,
+    statistics: Arc<Statistics>,
+    control: Arc<DeviceControl>,
+}
+
+/// A device's worker thread, restarted with backoff when it dies or on request. A stuck one is only reported,
+/// the watchdog's device action decides what happens to it.
+struct Supervised {
+    id: String,
+    launch: WorkerLaunch,
+    handle: Option<MinerHandler>,
+    started: Instant,
+    /// Restarts since the worker last kept running for `RESTART_RESET`
+    failures: u32,
+    restart_at: Option<Instant>,
+    stalled: bool,
+}
+
+impl Supervised {
+    fn start(id: String, launch: WorkerLaunch) -> Self {
+        let handle = MinerManager::launch_gpu_miner(launch.clone());
+        Self {
+            id,
+            launch,
+            handle: Some(handle),
+            started: Instant::now(),
+            failures: 0,
+            restart_at: None,
+            stalled: false,
+        }
+    }
+
+    fn check(&mut self) {
+        let stalled = self.launch.control.health.stalled();
+        if stalled != self.stalled {
+            // A kernel that never returns can't be interrupted, and its thread keeps the device's context and DAG,
+            // so no new worker is started next to it
+            if stalled {
+                error!("{}: worker stuck in a kernel run for more than {}s", self.id, STALL_TIMEOUT.as_secs());
+            }
+            self.stalled = stalled;
+            self.launch.statistics.device_stalled(&self.id, stalled);
+        }
+        if let Some(restart_at) = self.restart_at {
+            // The previous thread has to let go of the device first
+            if Instant::now() < restart_at || self.handle.as_ref().is_some_and(|handle| !handle.is_finished()) {
+                return;
+            }
+            if let Some(handle) = self.handle.take() {
+                _ = handle.join();
+            }
+            info!("{}: restarting worker (attempt {}/{})", self.id, self.failures, MAX_RESTARTS);
+            self.restart_at = None;
+            self.started = Instant::now();
+            self.handle = Some(MinerManager::launch_gpu_miner(self.launch.clone()));
+            self.launch.statistics.device_restarted(&self.id);
+            return;
+        }
+        let reason = match &self.handle {
+            Some(handle) if handle.is_finished() => match self.handle.take().unwrap().join() {
+                Ok(Ok(())) => "stopped".to_string(),
+                Ok(Err(e)) => format!("crashed: {}", e),
+                Err(_) => "panicked".to_string(),
+            },
+            Some(_) if self.launch.control.restart.swap(false, Ordering::AcqRel) => {
+                // The old thread quits before its next kernel run, the new one is started once it has
+                self.launch.control.health.generation.fetch_add(1, Ordering::AcqRel);
+                "restart requested".to_string()
+            }
+            _ => return,
+        };
+        if self.started.elapsed() >= RESTART_RESET {
+            self.failures = 0;
+        }
+        if self.failures == MAX_RESTARTS {
+            error!("{}: worker {}, giving up after {} restarts", self.id, reason, MAX_RESTARTS);
+            return;
+        }
+        let backoff = (RESTART_BACKOFF * 2u32.pow(self.failures)).min(RESTART_BACKOFF_MAX);
+        self.failures += 1;
+        warn!("{}: worker {}, restarting in {}s", self.id, reason, backoff.as_secs());
+        self.restart_at = Some(Instant::now() + backoff);
+    }
+}
+
 #[derive(Clone)]
 enum WorkerCommand {
     Job(Box<pow::State>),
     Close,
 }
 
+/// Hands the jobs to the workers. While held, e.g. outside the mining schedule, they get none but keep
+/// their DAG, and the latest job is handed over on release.
+struct JobGate {
+    channel: watch::Sender<Option<WorkerCommand>>,
+    /// Whether the gate is held, and the job to hand over on release
+    state: Mutex<(bool, Option<WorkerCommand>)>,
+}
+
+impl JobGate {
+    fn send(&self, job: Option<WorkerCommand>) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        state.1 = job.clone();
+        if !state.0 {
+            self.channel.send(job).map_err(|_e| "Failed sending block to threads")?;
+        }
+        Ok(())
+    }
+
+    fn hold(&self, held: bool) {
+        let mut state = self.state.lock().unwrap();
+        if std::mem::replace(&mut state.0, held) != held {
+            _ = self.channel.send(if held { None } else { state.1.clone() });
+        }
+    }
+
+    fn close(&self) -> Result<(), watch::ChannelClosed> {
+        self.channel.send(Some(WorkerCommand::Close))
+    }
+}
+
 #[allow(dead_code)]
 pub struct MinerManager {
-    handles: Vec<MinerHandler>,
-    block_channel: watch::Sender<Option<WorkerCommand>>,
+    workers: Arc<Mutex<Vec<Supervised>>>,
+    jobs: Arc<JobGate>,
     send_channel: Sender<BlockSeed>,
     logger_handle: JoinHandle<()>,
+    sampler_handle: JoinHandle<()>,
+    supervisor_handle: JoinHandle<()>,
     is_synced: bool,
+    /// Jobs are only recorded, for replays that feed recorded shares back without workers
+    discard_jobs: bool,
+    /// Nonce mask of the last job, to warn once about one too narrow to partition
+    nonce_mask: u64,
     hashes_tried: Arc<AtomicU64>,
     hashes_by_worker: Arc<Mutex<HashMap<String, Arc<AtomicU64>>>>,
     current_state_id: AtomicUsize,
+    statistics: Arc<Statistics>,
+    controls: Arc<Controls>,
 }
 
 impl Drop for MinerManager {
     fn drop(&mut self) {
         info!("Closing miner");
         self.logger_handle.abort();
-        match self.block_channel.send(Some(WorkerCommand::Close)) {
+        self.sampler_handle.abort();
+        self.supervisor_handle.abort();
+        let mut workers = self.workers.lock().unwrap();
+        match self.jobs.close() {
             Ok(_) => {}
             Err(_) => warn!("All workers are already dead"),
         }
-        while let Some(handle) = self.handles.pop() {
+        while let Some(handle) = workers.pop().and_then(|worker| worker.handle) {
             let kill_switch = Arc::new(AtomicBool::new(true));
             trigger_freeze_handler(kill_switch.clone(), &handle);
             match handle.join() {
@@ -121,97 +366,193 @@ impl Drop for MinerManager {
     }
 }
 
+#[async_trait(?Send)]
+impl JobSink for MinerManager {
+    async fn process_block(&mut self, block: Option<BlockSeed>) -> Result<(), Error> {
+        let state = match block {
+            Some(b) => {
+                self.is_synced = true;
+                let job_id = match b {
+                    BlockSeed::PartialBlock { ref id, .. } => Some(id.clone()),
+                    BlockSeed::FullBlock(_) => None,
+                };
+                let id = self.current_state_id.fetch_add(1, Ordering::SeqCst);
+                let state = pow::State::new(id, b)?;
+                self.check_nonce_mask(state.nonce_mask);
+                self.statistics.new_job(job_id, state.target, state.nonce_mask, state.nonce_fixed);
+                Some(WorkerCommand::Job(Box::new(state)))
+            }
+            None => {
+                if !self.is_synced {
+                    return Ok(());
+                }
+                self.is_synced = false;
+                warn!("Karlsend is not synced, skipping current template");
+                None
+            }
+        };
+
+        if self.discard_jobs {
+            return Ok(());
+        }
+        self.jobs.send(state)
+    }
+}
+
 const LOG_RATE: Duration = Duration::from_secs(30);
+const SAMPLE_RATE: Duration = Duration::from_secs(1);
+const PAUSE_CHECK_RATE: Duration = Duration::from_millis(100);
+const TELEMETRY_RATE: Duration = Duration::from_secs(10);
+const SUPERVISE_RATE: Duration = Duration::from_secs(1);
+/// Kernel runs take well under a second, one that takes this long is not coming back
+const STALL_TIMEOUT: Duration = Duration::from_secs(30);
+const RESTART_BACKOFF: Duration = Duration::from_secs(5);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(300);
+const MAX_RESTARTS: u32 = 10;
+/// A worker running this long is healthy again, its earlier restarts no longer count towards the cap
+const RESTART_RESET: Duration = Duration::from_secs(600);
+/// Fewer accepted shares are too noisy to judge the effective hashrate by
+const EFFECTIVE_MIN_SHARES: usize = 100;
+const EFFECTIVE_WARN_RATIO: f64 = 0.8;
 
 impl MinerManager {
-    pub fn new(send_channel: Sender<BlockSeed>, manager: &PluginManager) -> Self {
+    pub fn new(send_channel: Sender<BlockSeed>, manager: &PluginManager, statistics: Arc<Statistics>) -> Self {
         register_freeze_handler();
         let hashes_tried = Arc::new(AtomicU64::new(0));
         let hashes_by_worker = Arc::new(Mutex::new(HashMap::<String, Arc<AtomicU64>>::new()));
         let (send, recv) = watch::channel(None);
+        let jobs = Arc::new(JobGate { channel: send, state: Mutex::new((false, None)) });
+        let mut controls = Controls { devices: Vec::new(), jobs: jobs.clone(), statistics: statistics.clone() };
 
-        let handles = if manager.has_specs() {
+        let workers = if manager.has_specs() {
             Self::launch_gpu_threads(
                 send_channel.clone(),
                 Arc::clone(&hashes_tried),
                 recv,
                 manager,
                 hashes_by_worker.clone(),
+                &statistics,
+                &mut controls.devices,
             )
         } else {
             warn!("No GPU specs available, no miners will be launched");
             Vec::new()
         };
 
+        let workers = Arc::new(Mutex::new(workers));
         Self {
-            handles,
-            block_channel: send,
+            workers: workers.clone(),
+            jobs,
             send_channel,
-            logger_handle: task::spawn(Self::log_hashrate(Arc::clone(&hashes_tried), hashes_by_worker.clone())),
+            logger_handle: task::spawn(Self::log_hashrate(
+                Arc::clone(&hashes_tried),
+                hashes_by_worker.clone(),
+                statistics.clone(),
+            )),
+            sampler_handle: task::spawn(Self::sample_hashrate(hashes_by_worker.clone(), statistics.clone())),
+            supervisor_handle: task::spawn(Self::supervise(workers)),
             is_synced: true,
+            discard_jobs: false,
+            nonce_mask: u64::MAX,
             hashes_tried,
             current_state_id: AtomicUsize::new(0),
             hashes_by_worker,
+            statistics,
+            controls: Arc::new(controls),
         }
     }
 
+    fn check_nonce_mask(&mut self, nonce_mask: u64) {
+        if nonce_mask == self.nonce_mask {
+            return;
+        }
+        self.nonce_mask = nonce_mask;
+        let count = self.controls.devices.len();
+        if !(NoncePartition { index: 0, count }.fits(nonce_mask)) {
+            warn!(
+                "The pool leaves {} free nonce bits, too few to split between {} devices, some of them search the same nonces",
+                nonce_mask.count_ones(),
+                count
+            );
+        }
+    }
+
+    /// Accepts jobs without handing them to workers, instead of failing when there are none
+    pub fn discard_jobs(&mut self) {
+        self.discard_jobs = true;
+    }
+
+    pub fn controls(&self) -> Arc<Controls> {
+        self.controls.clone()
+    }
+
+    /// Stops the workers until the next job, e.g. while the client reconnects
+    pub fn idle(&mut self) {
+        self.is_synced = false;
+        _ = self.jobs.send(None);
+    }
+
     fn launch_gpu_threads(
         send_channel: Sender<BlockSeed>,
         hashes_tried: Arc<AtomicU64>,
         work_channel: watch::Receiver<Option<WorkerCommand>>,
         manager: &PluginManager,
         hashes_by_worker: Arc<Mutex<HashMap<String, Arc<AtomicU64>>>>,
-    ) -> Vec<MinerHandler> {
-        let mut vec = Vec::<MinerHandler>::new();
+        statistics: &Arc<Statistics>,
+        controls: &mut Vec<(String, Arc<DeviceControl>)>,
+    ) -> Vec<Supervised> {
+        let mut vec = Vec::<Supervised>::new();
         let specs = manager.build().unwrap();
-        for spec in specs {
+        let count = specs.len();
+        for (index, spec) in specs.into_iter().enumerate() {
+            let id = spec.id();
             let worker_hashes_tried = Arc::new(AtomicU64::new(0));
-            hashes_by_worker.lock().unwrap().insert(spec.id(), worker_hashes_tried.clone());
-            vec.push(Self::launch_gpu_miner(
-                send_channel.clone(),
-                work_channel.clone(),
-                Arc::clone(&hashes_tried),
-                spec,
+            hashes_by_worker.lock().unwrap().insert(id.clone(), worker_hashes_tried.clone());
+            statistics.add_device(&id);
+            let control = Arc::new(DeviceControl::default());
+            controls.push((id.clone(), control.clone()));
+            let launch = WorkerLaunch {
+                spec: Arc::from(spec),
+                send_channel: send_channel.clone(),
+                block_channel: work_channel.clone(),
+                hashes_tried: Arc::clone(&hashes_tried),
                 worker_hashes_tried,
-            ));
+                partition: NoncePartition { index, count },
+                statistics: statistics.clone(),
+                control,
+            };
+            vec.push(Supervised::start(id, launch));
         }
         vec
     }
 
-    pub async fn process_block(&mut self, block: Option<BlockSeed>) -> Result<(), Error> {
-        let state = match block {
-            Some(b) => {
-                self.is_synced = true;
-                let id = self.current_state_id.fetch_add(1, Ordering::SeqCst);
-                Some(WorkerCommand::Job(Box::new(pow::State::new(id, b)?)))
-            }
-            None => {
-                if !self.is_synced {
-                    return Ok(());
-                }
-                self.is_synced = false;
-                warn!("Karlsend is not synced, skipping current template");
-                None
-            }
-        };
-
-        self.block_channel.send(state).map_err(|_e| "Failed sending block to threads")?;
-        Ok(())
-    }
-
     #[allow(unreachable_code)]
-    fn launch_gpu_miner(
-        send_channel: Sender<BlockSeed>,
-        mut block_channel: watch::Receiver<Option<WorkerCommand>>,
-        hashes_tried: Arc<AtomicU64>,
-        spec: Box<dyn WorkerSpec>,
-        worker_hashes_tried: Arc<AtomicU64>,
-    ) -> MinerHandler {
+    fn launch_gpu_miner(launch: WorkerLaunch) -> MinerHandler {
+        let WorkerLaunch {
+            spec,
+            send_channel,
+            mut block_channel,
+            hashes_tried,
+            worker_hashes_tried,
+            partition,
+            statistics,
+            control,
+        } = launch;
+        let generation = control.health.generation.load(Ordering::Acquire);
         std::thread::spawn(move || {
+            let health = &control.health;
+            let device = spec.id();
             let mut box_ = spec.build();
             let gpu_work = box_.as_mut();
+            statistics.device_workload(&device, gpu_work.get_workload());
+            let mut telemetry_read = Instant::now();
             (|| {
-                info!("Spawned Thread for GPU {}", gpu_work.id());
+                info!(
+                    "Spawned Thread for GPU {} (nonce partition {}/{})",
+                    gpu_work.id(),
+                    partition.index + 1,
+                    partition.count
+                );
                 let mut nonces = vec![0u64; 1];
 
                 let mut state = None;
@@ -233,6 +574,27 @@ impl MinerManager {
                             }
                         };
                     }
+                    if let Some(workload) = control.workload.lock().unwrap().take() {
+                        match gpu_work.set_workload(workload) {
+                            Ok(()) => info!("{}: workload set to {}", gpu_work.id(), gpu_work.get_workload()),
+                            Err(e) => warn!("{}: failed setting workload to {}: {}", gpu_work.id(), workload, e),
+                        }
+                        statistics.device_workload(&device, gpu_work.get_workload());
+                    }
+                    if control.paused.load(Ordering::Acquire) {
+                        // Keeps following the jobs without running the kernel
+                        sleep(PAUSE_CHECK_RATE);
+                        if let Some(new_cmd) = block_channel.get_changed()? {
+                            state = match new_cmd {
+                                Some(WorkerCommand::Job(s)) => Some(s),
+                                Some(WorkerCommand::Close) => {
+                                    return Ok(());
+                                }
+                                None => None,
+                            };
+                        }
+                        continue;
+                    }
                     let state_ref = match &state {
                         Some(s) => {
                             s.load_to_gpu(gpu_work);
@@ -240,17 +602,36 @@ impl MinerManager {
                         }
                         None => continue,
                     };
-                    state_ref.pow_gpu(gpu_work);
-                    if let Err(e) = gpu_work.sync() {
+                    if health.generation.load(Ordering::Acquire) != generation {
+                        return Ok(());
+                    }
+                    health.busy(true);
+                    state_ref.pow_gpu(gpu_work, partition);
+                    let synced = gpu_work.sync();
+                    if health.generation.load(Ordering::Acquire) != generation {
+                        // The supervisor gave up on this run and started a new worker
+                        return Ok(());
+                    }
+                    if let Err(e) = synced {
+                        health.busy(false);
                         warn!("CUDA run ignored: {}", e);
                         continue;
                     }
 
-                    gpu_work.copy_output_to(&mut nonces)?;
+                    let copied = gpu_work.copy_output_to(&mut nonces);
+                    health.busy(false);
+                    copied?;
                     if nonces[0] != 0 {
                         if let Some(block_seed) = state_ref.generate_block_if_pow(nonces[0]) {
                             match send_channel.blocking_send(block_seed.clone()) {
-                                Ok(()) => block_seed.report_block(),
+                                Ok(()) => {
+                                    block_seed.report_block();
+                                    if let BlockSeed::FullBlock(ref block) = block_seed {
+                                        if let Some(hash) = block.block_hash() {
+                                            statistics.block_found(format!("{:x}", hash));
+                                        }
+                                    }
+                                }
                                 Err(e) => error!("Failed submitting block: ({})", e),
                             };
                             if let BlockSeed::FullBlock(_) = block_seed {
@@ -271,6 +652,11 @@ impl MinerManager {
                     hashes_tried.fetch_add(gpu_work.get_workload().try_into().unwrap(), Ordering::AcqRel);
                     worker_hashes_tried.fetch_add(gpu_work.get_workload().try_into().unwrap(), Ordering::AcqRel);
 
+                    if telemetry_read.elapsed() >= TELEMETRY_RATE {
+                        statistics.device_telemetry(&device, gpu_work.telemetry());
+                        telemetry_read = Instant::now();
+                    }
+
                     {
                         if let Some(new_cmd) = block_channel.get_changed()? {
                             state = match new_cmd {
@@ -291,35 +677,60 @@ impl MinerManager {
         })
     }
 
-    async fn log_hashrate(hashes_tried: Arc<AtomicU64>, hashes_by_worker: Arc<Mutex<HashMap<String, Arc<AtomicU64>>>>) {
+    async fn supervise(workers: Arc<Mutex<Vec<Supervised>>>) {
+        let mut ticker = tokio::time::interval(SUPERVISE_RATE);
+        loop {
+            ticker.tick().await;
+            workers.lock().unwrap().iter_mut().for_each(Supervised::check);
+        }
+    }
+
+    async fn log_hashrate(
+        hashes_tried: Arc<AtomicU64>,
+        hashes_by_worker: Arc<Mutex<HashMap<String, Arc<AtomicU64>>>>,
+        statistics: Arc<Statistics>,
+    ) {
         let mut ticker = tokio::time::interval(LOG_RATE);
         ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
         let mut last_instant = ticker.tick().await;
         loop {
             let now = ticker.tick().await;
             let duration = (now - last_instant).as_secs_f64();
-            Self::log_single_hashrate(
-                &hashes_tried,
+            let paused = statistics.devices().into_iter().filter(|device| device.paused).map(|device| device.id);
+            let paused = paused.collect::<Vec<_>>();
+            let hashes = Self::log_single_hashrate(
+                hashes_tried.swap(0, Ordering::AcqRel),
                 "Current hashrate is".into(),
-                "GPU workers stalled or crashed. Consider reducing workload and check that your node is synced.",
+                match !paused.is_empty() && paused.len() == hashes_by_worker.lock().unwrap().len() {
+                    true => "All GPU workers are paused.",
+                    false => {
+                        "GPU workers stalled or crashed. Consider reducing workload and check that your node is synced."
+                    }
+                },
                 duration,
                 false,
             );
-            for (device, rate) in &*hashes_by_worker.lock().unwrap() {
-                Self::log_single_hashrate(rate, format!("GPU Device {}:", device), "0 hash/s", duration, true);
+            let devices = hashes_by_worker.lock().unwrap().keys().cloned().collect::<Vec<_>>();
+            for device in &devices {
+                let hashes = statistics.sampled_hashes(device, last_instant.into_std(), now.into_std());
+                if paused.contains(device) {
+                    info!("GPU Device {}: paused", device);
+                    statistics.device_hashes(device, hashes, now - last_instant);
+                    continue;
+                }
+                Self::log_single_hashrate(hashes, format!("GPU Device {}:", device), "0 hash/s", duration, true);
+                statistics.device_hashes(device, hashes, now - last_instant);
+            }
+            if !devices.is_empty() {
+                Self::log_average_hashrate(&statistics.summary().hashrates);
             }
+            statistics.hashrate.tried(hashes);
+            Self::log_effective_hashrate(&statistics);
             last_instant = now;
         }
     }
 
-    fn log_single_hashrate(
-        counter: &Arc<AtomicU64>,
-        prefix: String,
-        warn_message: &str,
-        duration: f64,
-        keep_prefix: bool,
-    ) {
-        let hashes = counter.swap(0, Ordering::AcqRel);
+    fn log_single_hashrate(hashes: u64, prefix: String, warn_message: &str, duration: f64, keep_prefix: bool) -> u64 {
         let rate = (hashes as f64) / duration;
         if hashes == 0 {
             match keep_prefix {
@@ -330,10 +741,67 @@ impl MinerManager {
             let (rate, suffix) = Self::hash_suffix(rate);
             info!("{} {:.2} {}", prefix, rate, suffix);
         }
+        hashes
+    }
+
+    fn log_average_hashrate(hashrates: &Hashrates) {
+        let rate = |rate: f64| {
+            let (rate, suffix) = Self::hash_suffix(rate);
+            format!("{:.2} {}", rate, suffix)
+        };
+        let windows = hashrates.windows.iter().map(|window| format!("{}: {}", window.window, rate(window.hashrate)));
+        info!(
+            "Average hashrate {}, session: {} (peak {}, min {})",
+            windows.collect::<Vec<_>>().join(", "),
+            rate(hashrates.session),
+            rate(hashrates.peak),
+            rate(hashrates.min)
+        );
+    }
+
+    async fn sample_hashrate(
+        hashes_by_worker: Arc<Mutex<HashMap<String, Arc<AtomicU64>>>>,
+        statistics: Arc<Statistics>,
+    ) {
+        let mut ticker = tokio::time::interval(SAMPLE_RATE);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut last_instant = ticker.tick().await;
+        loop {
+            let now = ticker.tick().await;
+            let workers = hashes_by_worker.lock().unwrap();
+            let hashes = workers.iter().map(|(device, counter)| (device.clone(), counter.swap(0, Ordering::AcqRel)));
+            statistics.sample_hashes(&hashes.collect::<Vec<_>>(), now - last_instant);
+            last_instant = now;
+        }
+    }
+
+    /// Compares the hashrate proven by accepted shares with the measured one, once there are enough shares
+    fn log_effective_hashrate(statistics: &Statistics) {
+        for window in statistics.hashrate.snapshot().iter().filter(|window| window.shares > 0) {
+            let (rate, suffix) = Self::hash_suffix(window.effective);
+            match window.ratio() {
+                Some(ratio) if window.shares >= EFFECTIVE_MIN_SHARES && ratio < EFFECTIVE_WARN_RATIO => warn!(
+                    "Effective hashrate ({}) is {:.2} {}, only {:.0}% of the measured hashrate. Check for stale or rejected shares, unstable hardware or pool issues.",
+                    window.window,
+                    rate,
+                    suffix,
+                    ratio * 100.0
+                ),
+                Some(ratio) => info!(
+                    "Effective hashrate ({}): {:.2} {} ({:.0}% of measured, {} shares)",
+                    window.window,
+                    rate,
+                    suffix,
+                    ratio * 100.0,
+                    window.shares
+                ),
+                None => info!("Effective hashrate ({}): {:.2} {} ({} shares)", window.window, rate, suffix, window.shares),
+            }
+        }
     }
 
     #[inline]
-    fn hash_suffix(n: f64) -> (f64, &'static str) {
+    pub fn hash_suffix(n: f64) -> (f64, &'static str) {
         match n {
             n if n < 1_000.0 => (n, "hash/s"),
             n if n < 1_000_000.0 => (n / 1_000.0, "Khash/s"),
@@ -345,6 +813,61 @@ impl MinerManager {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::{Supervised, WorkerLaunch, MAX_RESTARTS};
+    use crate::pow::NoncePartition;
+    use crate::stats::Statistics;
+    use crate::watch;
+    use karlsen_miner::{Worker, WorkerSpec};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    struct BrokenSpec;
+
+    impl WorkerSpec for BrokenSpec {
+        fn id(&self) -> String {
+            "#0 (Broken GPU)".into()
+        }
+
+        fn build(&self) -> Box<dyn Worker> {
+            panic!("device lost");
+        }
+    }
+
+    #[test]
+    fn test_supervised_restarts() {
+        let statistics = Arc::new(Statistics::default());
+        let launch = WorkerLaunch {
+            spec: Arc::new(BrokenSpec),
+            send_channel: tokio::sync::mpsc::channel(1).0,
+            block_channel: watch::channel(None).1,
+            hashes_tried: Default::default(),
+            worker_hashes_tried: Default::default(),
+            partition: NoncePartition { index: 0, count: 1 },
+            statistics: statistics.clone(),
+            control: Default::default(),
+        };
+        let mut worker = Supervised::start(BrokenSpec.id(), launch);
+        for attempt in 1..=MAX_RESTARTS {
+            while !worker.handle.as_ref().unwrap().is_finished() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            worker.check();
+            assert_eq!(worker.failures, attempt);
+            assert!(worker.handle.is_none() && worker.restart_at.is_some());
+            worker.restart_at = Some(Instant::now());
+            worker.check();
+        }
+        assert_eq!(statistics.devices()[0].restarts, MAX_RESTARTS as u64);
+        while !worker.handle.as_ref().unwrap().is_finished() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        worker.check();
+        assert!(worker.handle.is_none() && worker.restart_at.is_none());
+    }
+}
+
 #[cfg(all(test, feature = "bench"))]
 mod benches {
     extern crate test;
@@ -133,8 +133,41 @@ impl State {
     }
 
     #[inline(always)]
-    pub fn pow_gpu(&self, gpu_work: &mut dyn Worker) {
-        gpu_work.calculate_hash(None, self.nonce_mask, self.nonce_fixed);
+    pub fn pow_gpu(&self, gpu_work: &mut dyn Worker, partition: NoncePartition) {
+        let (nonce_mask, nonce_fixed) = partition.apply(self.nonce_mask, self.nonce_fixed);
+        gpu_work.calculate_hash(None, nonce_mask, nonce_fixed);
+    }
+}
+
+/// The part of the nonce space one of `count` workers searches, so devices never try the same nonce
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NoncePartition {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl NoncePartition {
+    /// Number of nonce bits the partition takes out of `nonce_mask`
+    pub fn bits(&self, nonce_mask: u64) -> u32 {
+        self.count.next_power_of_two().trailing_zeros().min(nonce_mask.count_ones())
+    }
+
+    /// Whether `nonce_mask` has a free bit for every bit of the worker index. Workers of a partition that
+    /// doesn't fit share the nonces they search.
+    pub fn fits(&self, nonce_mask: u64) -> bool {
+        self.count.next_power_of_two().trailing_zeros() <= nonce_mask.count_ones()
+    }
+
+    /// Fixes the highest free bits of `nonce_mask` to the worker index
+    pub fn apply(&self, mut nonce_mask: u64, mut nonce_fixed: u64) -> (u64, u64) {
+        for bit in (0..self.bits(nonce_mask)).rev() {
+            let top = 1u64 << (63 - nonce_mask.leading_zeros());
+            nonce_mask &= !top;
+            if (self.index >> bit) & 1 == 1 {
+                nonce_fixed |= top;
+            }
+        }
+        (nonce_mask, nonce_fixed)
     }
 }
 
@@ -222,3 +255,28 @@ fn decode_to_slice<T: AsRef<[u8]>>(data: T, out: &mut [u8]) -> Result<(), FromHe
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::NoncePartition;
+
+    #[test]
+    fn test_nonce_partition() {
+        let whole = NoncePartition { index: 0, count: 1 };
+        assert_eq!(whole.apply(u64::MAX, 0), (u64::MAX, 0));
+
+        // Three workers take the top two bits of the mask left over by a 2 byte extranonce
+        let (mask, fixed) = (0x0000_ffff_ffff_ffff, 0xa5a5_0000_0000_0000);
+        let partitions = (0..3).map(|index| NoncePartition { index, count: 3 }.apply(mask, fixed)).collect::<Vec<_>>();
+        assert_eq!(partitions[0], (0x0000_3fff_ffff_ffff, 0xa5a5_0000_0000_0000));
+        assert_eq!(partitions[1], (0x0000_3fff_ffff_ffff, 0xa5a5_4000_0000_0000));
+        assert_eq!(partitions[2], (0x0000_3fff_ffff_ffff, 0xa5a5_8000_0000_0000));
+
+        // Never takes more bits than the mask has, so a mask too narrow for the workers makes them overlap
+        assert!(NoncePartition { index: 0, count: 4 }.fits(0b110));
+        assert!(!NoncePartition { index: 0, count: 4 }.fits(0b100));
+        assert!(!NoncePartition { index: 0, count: 3 }.fits(0b100));
+        assert_eq!(NoncePartition { index: 3, count: 4 }.apply(0b100, 0), (0, 0b100));
+        assert_eq!(NoncePartition { index: 1, count: 4 }.apply(0b100, 0), (0, 0b100));
+    }
+}
@@ -0,0 +1,240 @@
+//! Pauses mining on a weekly schedule, e.g. during peak electricity hours, and while a file exists or a
+//! process runs. The workers are sent no jobs while paused but keep their DAG, so mining resumes right away.
+
+use jiff::tz::TimeZone;
+use jiff::Timestamp;
+use log::{error, info};
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::cli::Opt;
+use crate::config::Settings;
+use crate::miner::Controls;
+use crate::Error;
+
+const CHECK_RATE: Duration = Duration::from_secs(10);
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// A weekly time range such as `mon-fri 17:00-21:00`. Without days it applies every day, a range ending
+/// before it starts runs past midnight into the next day.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PauseWindow {
+    days: [bool; 7],
+    /// Minutes since midnight
+    start: u16,
+    end: u16,
+    text: String,
+}
+
+impl FromStr for PauseWindow {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("Expected a time range such as \"mon-fri 17:00-21:00\" or \"22:00-06:00\", got {}", s);
+        let (days, times) = match s.trim().rsplit_once(' ') {
+            Some((days, times)) => (parse_days(days.trim()).ok_or_else(err)?, times),
+            None => ([true; 7], s.trim()),
+        };
+        let (start, end) = times.split_once('-').ok_or_else(err)?;
+        let (start, end) = (parse_time(start).ok_or_else(err)?, parse_time(end).ok_or_else(err)?);
+        if start == end || start == MINUTES_PER_DAY {
+            return Err(err().into());
+        }
+        Ok(Self { days, start, end: end % MINUTES_PER_DAY, text: s.trim().to_string() })
+    }
+}
+
+impl Display for PauseWindow {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// `mon-fri`, `sat,sun`, `mon,wed-fri` or `daily`
+fn parse_days(s: &str) -> Option<[bool; 7]> {
+    if s.eq_ignore_ascii_case("daily") {
+        return Some([true; 7]);
+    }
+    let day = |name: &str| DAYS.iter().position(|day| name.trim().eq_ignore_ascii_case(day));
+    let mut days = [false; 7];
+    for part in s.split(',') {
+        let (first, last) = part.split_once('-').unwrap_or((part, part));
+        let (first, last) = (day(first)?, day(last)?);
+        // A range such as sat-mon wraps around the week
+        let count = (last + 7 - first) % 7 + 1;
+        (0..count).for_each(|offset| days[(first + offset) % 7] = true);
+    }
+    Some(days)
+}
+
+/// `HH:MM` as minutes since midnight, up to `24:00`
+fn parse_time(s: &str) -> Option<u16> {
+    let (hours, minutes) = s.trim().split_once(':')?;
+    let (hours, minutes) = (hours.parse::<u16>().ok()?, minutes.parse::<u16>().ok()?);
+    let time = hours * 60 + minutes;
+    (minutes < 60 && time <= MINUTES_PER_DAY).then_some(time)
+}
+
+impl PauseWindow {
+    /// `day` counts from Monday as 0
+    fn contains(&self, day: usize, minute: u16) -> bool {
+        if self.start < self.end || self.end == 0 {
+            let end = if self.end == 0 { MINUTES_PER_DAY } else { self.end };
+            return self.days[day] && (self.start..end).contains(&minute);
+        }
+        (self.days[day] && minute >= self.start) || (self.days[(day + 6) % 7] && minute < self.end)
+    }
+}
+
+/// The system's time zone, or the named one
+pub fn timezone(name: &Option<String>) -> Result<TimeZone, Error> {
+    match name {
+        Some(name) => TimeZone::get(name).map_err(|e| format!("Unknown time zone {}: {}", name, e).into()),
+        None => Ok(TimeZone::system()),
+    }
+}
+
+pub struct Schedule {
+    windows: Vec<PauseWindow>,
+    timezone: TimeZone,
+    file: Option<PathBuf>,
+    processes: Vec<String>,
+}
+
+impl Schedule {
+    pub fn new(opt: &Opt) -> Result<Self, Error> {
+        Ok(Self {
+            windows: opt.pause_schedule.clone(),
+            timezone: timezone(&opt.schedule_timezone)?,
+            file: opt.pause_file.clone(),
+            processes: opt.pause_process.clone(),
+        })
+    }
+
+    /// Why mining should be paused at `now`, if it should
+    fn reason(&self, now: Timestamp, running: impl FnOnce() -> Vec<String>) -> Option<String> {
+        let zoned = now.to_zoned(self.timezone.clone());
+        let day = zoned.weekday().to_monday_zero_offset() as usize;
+        let minute = zoned.hour() as u16 * 60 + zoned.minute() as u16;
+        if let Some(window) = self.windows.iter().find(|window| window.contains(day, minute)) {
+            return Some(format!("scheduled pause {}", window));
+        }
+        if let Some(file) = self.file.as_ref().filter(|file| file.exists()) {
+            return Some(format!("{} exists", file.display()));
+        }
+        if self.processes.is_empty() {
+            return None;
+        }
+        let running = running();
+        let process = self.processes.iter().find(|name| running.iter().any(|process| same_process(process, name)))?;
+        Some(format!("{} is running", process))
+    }
+}
+
+/// Case does not matter, nor does `.exe`. Linux cuts process names to 15 characters.
+fn same_process(process: &str, name: &str) -> bool {
+    let (process, name) = (process.to_lowercase(), name.to_lowercase());
+    let (process, name) = (process.trim_end_matches(".exe"), name.trim_end_matches(".exe"));
+    process == name || (cfg!(target_os = "linux") && process.len() == 15 && name.starts_with(process))
+}
+
+/// Names of the running processes, empty when they can't be listed
+fn running_processes() -> Vec<String> {
+    if cfg!(target_os = "linux") {
+        let entries = std::fs::read_dir("/proc").into_iter().flatten().flatten();
+        return entries
+            .filter_map(|entry| std::fs::read_to_string(entry.path().join("comm")).ok())
+            .map(|name| name.trim_end().to_string())
+            .collect();
+    }
+    let output = match cfg!(target_os = "windows") {
+        true => Command::new("tasklist").args(["/FO", "CSV", "/NH"]).output(),
+        false => Command::new("ps").args(["-A", "-o", "comm="]).output(),
+    };
+    let output = match output {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).into_owned(),
+        Err(e) => {
+            error!("Failed listing processes: {}", e);
+            return Vec::new();
+        }
+    };
+    output
+        .lines()
+        // tasklist quotes its columns, ps gives full paths
+        .map(|line| line.split("\",").next().unwrap_or_default().trim_matches('"'))
+        .map(|name| name.rsplit(['/', '\\']).next().unwrap_or_default().to_string())
+        .collect()
+}
+
+/// Pauses and resumes the workers as the schedule and the conditions say, following reloaded settings
+pub async fn run(controls: Arc<Controls>, settings: Settings) {
+    let mut ticker = tokio::time::interval(CHECK_RATE);
+    let mut held = None::<String>;
+    loop {
+        ticker.tick().await;
+        let schedule = match Schedule::new(&settings.borrow()) {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                error!("Ignoring the mining schedule: {}", e);
+                continue;
+            }
+        };
+        let reason = schedule.reason(Timestamp::now(), running_processes);
+        if reason == held {
+            continue;
+        }
+        match &reason {
+            Some(reason) => info!("Pausing mining, {}", reason),
+            None => info!("Resuming mining"),
+        }
+        controls.hold(reason.as_deref());
+        held = reason;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PauseWindow, Schedule};
+    use crate::cli::Opt;
+    use clap::Parser;
+    use jiff::Timestamp;
+
+    #[test]
+    fn test_schedule() {
+        let window = |s: &str| s.parse::<PauseWindow>().unwrap();
+        assert!(window("mon-fri 17:00-21:00").contains(4, 17 * 60));
+        assert!(!window("mon-fri 17:00-21:00").contains(5, 17 * 60));
+        assert!(window("fri 22:00-06:00").contains(5, 5 * 60 + 59));
+        assert!(!window("fri 22:00-06:00").contains(4, 5 * 60));
+        assert!(window("sat-mon 20:00-24:00").contains(0, 23 * 60 + 59));
+        assert!(!window("sat-mon 20:00-24:00").contains(1, 21 * 60));
+        assert!(window("12:00-13:00").contains(2, 12 * 60 + 30));
+        for bad in ["mon-fri", "mon-fry 17:00-21:00", "17:00-17:00", "17:00-25:00", "7:60-8:00"] {
+            assert!(bad.parse::<PauseWindow>().is_err(), "{}", bad);
+        }
+
+        let opt = Opt::parse_from([
+            "karlsen-miner",
+            "-a",
+            "karlsen:miner",
+            "--pause-schedule",
+            "mon-fri 17:00-21:00",
+            "--schedule-timezone",
+            "Europe/Berlin",
+            "--pause-process",
+            "Game.exe",
+        ]);
+        let schedule = Schedule::new(&opt).unwrap();
+        // Wednesday 2026-10-21 at 17:30 in Berlin, which is 15:30 UTC
+        let peak = "2026-10-21T15:30:00Z".parse::<Timestamp>().unwrap();
+        let evening = "2026-10-21T19:30:00Z".parse::<Timestamp>().unwrap();
+        assert_eq!(schedule.reason(peak, Vec::new).unwrap(), "scheduled pause mon-fri 17:00-21:00");
+        assert_eq!(schedule.reason(evening, Vec::new), None);
+        assert_eq!(schedule.reason(evening, || vec!["game".into()]).unwrap(), "Game.exe is running");
+    }
+}
@@ -0,0 +1,140 @@
+//! Orderly shutdown on SIGINT or SIGTERM: the workers finish their kernel runs, the shares they found are
+//! submitted and answered, then the session is summed up. A second signal exits right away.
+
+use log::{info, warn};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::time::sleep;
+
+use crate::miner::{Controls, MinerManager};
+use crate::stats::Statistics;
+
+/// Exit code after a second signal, as a shell reports a process killed by SIGINT
+pub const FORCED_EXIT_CODE: i32 = 130;
+const KERNEL_DEADLINE: Duration = Duration::from_secs(5);
+const SHARE_DEADLINE: Duration = Duration::from_secs(5);
+/// Time for the last shares to travel from the workers to the client
+const SHARE_FORWARD: Duration = Duration::from_millis(500);
+const POLL_RATE: Duration = Duration::from_millis(100);
+
+static REQUESTED: Notify = Notify::const_new();
+static EXIT_CODE: AtomicI32 = AtomicI32::new(0);
+
+/// Asks for a shutdown as a signal would, e.g. from the dashboard where raw mode swallows Ctrl-C
+pub fn request() {
+    REQUESTED.notify_one();
+}
+
+/// Shuts down as `request` does, then exits with `code`, e.g. for a supervisor to restart the miner
+pub fn request_exit(code: i32) {
+    EXIT_CODE.store(code, Ordering::Release);
+    request();
+}
+
+/// The code to exit with once shut down, 0 unless `request_exit` asked for another
+pub fn exit_code() -> i32 {
+    EXIT_CODE.load(Ordering::Acquire)
+}
+
+/// Resolves on SIGINT, SIGTERM or a `request`
+pub async fn requested() {
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate() => {}
+        _ = REQUESTED.notified() => {}
+    }
+}
+
+async fn terminate() {
+    #[cfg(unix)]
+    if let Ok(mut signal) = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+        signal.recv().await;
+        return;
+    }
+    std::future::pending::<()>().await
+}
+
+/// Stops the workers and waits for the shares still in flight. The client has to keep running meanwhile.
+pub async fn drain(controls: &Controls, statistics: &Statistics) {
+    info!("Shutting down, signal again to exit right away");
+    tokio::spawn(async {
+        requested().await;
+        warn!("Exiting without waiting for the workers");
+        std::process::exit(FORCED_EXIT_CODE);
+    });
+
+    controls.stop();
+    let deadline = Instant::now() + KERNEL_DEADLINE;
+    sleep(POLL_RATE).await;
+    while controls.busy() {
+        if Instant::now() >= deadline {
+            warn!("Workers are still running after {}s, not waiting for them", KERNEL_DEADLINE.as_secs());
+            break;
+        }
+        sleep(POLL_RATE).await;
+    }
+
+    sleep(SHARE_FORWARD).await;
+    let deadline = Instant::now() + SHARE_DEADLINE;
+    loop {
+        let pending = statistics.snapshot().sessions.iter().map(|session| session.pending).sum::<usize>();
+        if pending == 0 {
+            break;
+        }
+        if Instant::now() >= deadline {
+            warn!("{} shares left unanswered", pending);
+            break;
+        }
+        sleep(POLL_RATE).await;
+    }
+}
+
+pub fn log_summary(statistics: &Statistics) {
+    let summary = statistics.summary();
+    let (rate, suffix) = MinerManager::hash_suffix(summary.hashrates.session);
+    let blocks = statistics.blocks();
+    info!("=================================================================================");
+    info!(
+        " Uptime: {}h {:02}m {:02}s",
+        summary.uptime_secs / 3600,
+        summary.uptime_secs / 60 % 60,
+        summary.uptime_secs % 60
+    );
+    info!(" Average hashrate: {:.2} {}", rate, suffix);
+    info!(" Shares: {}", statistics.snapshot().lifetime);
+    info!(
+        " Blocks: {} found, {} submitted, {} rejected, {} duplicates dropped",
+        blocks.found, blocks.submitted, blocks.rejected, blocks.dropped_duplicate
+    );
+    info!("=================================================================================");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{drain, SHARE_DEADLINE, SHARE_FORWARD};
+    use crate::miner::MinerManager;
+    use crate::stats::Statistics;
+    use karlsen_miner::PluginManager;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_drain() {
+        let statistics = Arc::new(Statistics::default());
+        let miner_manager = MinerManager::new(mpsc::channel(1).0, &PluginManager::new(), statistics.clone());
+        let session = statistics.start_session("pool:5555");
+        session.submitted(1, "7".into());
+        let answered = session.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(SHARE_FORWARD + Duration::from_millis(300)).await;
+            answered.answer(1);
+        });
+
+        let started = Instant::now();
+        drain(&miner_manager.controls(), &statistics).await;
+        assert!(started.elapsed() < SHARE_FORWARD + SHARE_DEADLINE);
+        assert_eq!(statistics.snapshot().sessions[0].pending, 0);
+    }
+}
@@ -0,0 +1,880 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::{self, Display, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::Uint256;
+
+const LATENCY_SAMPLES: usize = 1000;
+const RECENT_BLOCKS: usize = 32;
+/// Windows the effective hashrate is estimated over
+pub const HASHRATE_WINDOWS: [(&str, Duration); 3] = [
+    ("15m", Duration::from_secs(15 * 60)),
+    ("1h", Duration::from_secs(60 * 60)),
+    ("6h", Duration::from_secs(6 * 60 * 60)),
+];
+/// Windows the measured hashrate is averaged over, next to the whole session
+pub const AVERAGE_WINDOWS: [(&str, Duration); 3] =
+    [("10s", Duration::from_secs(10)), ("60s", Duration::from_secs(60)), ("15m", Duration::from_secs(15 * 60))];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShareEvent {
+    Accepted,
+    Stale,
+    LowDifficulty,
+    Duplicate,
+    /// Never answered by the pool
+    Lost,
+    /// Not submitted, the job was already invalidated
+    Suppressed,
+    /// Not submitted, the nonce was already submitted for the job
+    DroppedDuplicate,
+}
+
+#[derive(Default)]
+struct ShareCounters {
+    accepted: AtomicU64,
+    stale: AtomicU64,
+    low_difficulty: AtomicU64,
+    duplicate: AtomicU64,
+    lost: AtomicU64,
+    suppressed: AtomicU64,
+    dropped_duplicate: AtomicU64,
+}
+
+impl ShareCounters {
+    fn add(&self, event: ShareEvent, count: u64) {
+        let counter = match event {
+            ShareEvent::Accepted => &self.accepted,
+            ShareEvent::Stale => &self.stale,
+            ShareEvent::LowDifficulty => &self.low_difficulty,
+            ShareEvent::Duplicate => &self.duplicate,
+            ShareEvent::Lost => &self.lost,
+            ShareEvent::Suppressed => &self.suppressed,
+            ShareEvent::DroppedDuplicate => &self.dropped_duplicate,
+        };
+        counter.fetch_add(count, Ordering::SeqCst);
+    }
+
+    fn snapshot(&self) -> ShareCounts {
+        ShareCounts {
+            accepted: self.accepted.load(Ordering::SeqCst),
+            stale: self.stale.load(Ordering::SeqCst),
+            low_difficulty: self.low_difficulty.load(Ordering::SeqCst),
+            duplicate: self.duplicate.load(Ordering::SeqCst),
+            lost: self.lost.load(Ordering::SeqCst),
+            suppressed: self.suppressed.load(Ordering::SeqCst),
+            dropped_duplicate: self.dropped_duplicate.load(Ordering::SeqCst),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ShareCounts {
+    pub accepted: u64,
+    pub stale: u64,
+    pub low_difficulty: u64,
+    pub duplicate: u64,
+    pub lost: u64,
+    pub suppressed: u64,
+    pub dropped_duplicate: u64,
+}
+
+impl std::ops::AddAssign for ShareCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.accepted += other.accepted;
+        self.stale += other.stale;
+        self.low_difficulty += other.low_difficulty;
+        self.duplicate += other.duplicate;
+        self.lost += other.lost;
+        self.suppressed += other.suppressed;
+        self.dropped_duplicate += other.dropped_duplicate;
+    }
+}
+
+impl Display for ShareCounts {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        [
+            ("Accepted", self.accepted),
+            ("Stale", self.stale),
+            ("Low difficulty", self.low_difficulty),
+            ("Duplicate", self.duplicate),
+            ("Lost", self.lost),
+            ("Suppressed", self.suppressed),
+            ("Dropped duplicates", self.dropped_duplicate),
+        ]
+        .iter()
+        .filter(|(_, count)| *count > 0)
+        .try_for_each(|(name, count)| write!(f, "{}: {} ", name, count))
+    }
+}
+
+/// Round trip times of the last `LATENCY_SAMPLES` answered shares
+#[derive(Default)]
+pub struct ShareLatency {
+    samples: VecDeque<Duration>,
+}
+
+impl ShareLatency {
+    fn record(&mut self, latency: Duration) {
+        if self.samples.len() == LATENCY_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency);
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.samples.iter().min().copied()
+    }
+
+    pub fn avg(&self) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        Some(self.samples.iter().sum::<Duration>() / self.samples.len() as u32)
+    }
+
+    pub fn p95(&self) -> Option<Duration> {
+        let mut sorted = self.samples.iter().copied().collect::<Vec<_>>();
+        sorted.sort_unstable();
+        sorted.get((sorted.len() * 95).div_ceil(100).checked_sub(1)?).copied()
+    }
+
+    fn snapshot(&self) -> LatencySnapshot {
+        let ms = |d: Option<Duration>| d.map(|d| d.as_secs_f64() * 1000.0);
+        LatencySnapshot { min_ms: ms(self.min()), avg_ms: ms(self.avg()), p95_ms: ms(self.p95()) }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Serialize)]
+pub struct LatencySnapshot {
+    pub min_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+}
+
+impl Display for LatencySnapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let ms = |ms: Option<f64>| ms.unwrap_or_default();
+        write!(f, "min: {:.0}ms avg: {:.0}ms p95: {:.0}ms", ms(self.min_ms), ms(self.avg_ms), ms(self.p95_ms))
+    }
+}
+
+#[derive(Default)]
+struct PoolStats {
+    shares: ShareCounters,
+    latency: Mutex<ShareLatency>,
+}
+
+/// Work proven by accepted shares, next to the hashes the workers say they tried
+pub struct HashrateMonitor {
+    started: Instant,
+    accepted: Mutex<VecDeque<(Instant, f64)>>,
+    tried: Mutex<VecDeque<(Instant, f64)>>,
+}
+
+impl Default for HashrateMonitor {
+    fn default() -> Self {
+        Self { started: Instant::now(), accepted: Default::default(), tried: Default::default() }
+    }
+}
+
+impl HashrateMonitor {
+    /// An accepted share, worth `work` hashes on average at its difficulty
+    pub fn accepted(&self, work: f64) {
+        Self::push(&self.accepted, work);
+    }
+
+    pub fn tried(&self, hashes: u64) {
+        Self::push(&self.tried, hashes as f64);
+    }
+
+    fn push(samples: &Mutex<VecDeque<(Instant, f64)>>, value: f64) {
+        let mut samples = samples.lock().unwrap();
+        let longest = HASHRATE_WINDOWS[HASHRATE_WINDOWS.len() - 1].1;
+        while samples.front().is_some_and(|(at, _)| at.elapsed() > longest) {
+            samples.pop_front();
+        }
+        samples.push_back((Instant::now(), value));
+    }
+
+    /// Windows are cut short while the miner has been running for less than their length
+    pub fn snapshot(&self) -> Vec<EffectiveHashrate> {
+        let (accepted, tried) = (self.accepted.lock().unwrap(), self.tried.lock().unwrap());
+        HASHRATE_WINDOWS
+            .iter()
+            .map(|&(window, length)| {
+                let span = length.min(self.started.elapsed()).as_secs_f64().max(1.0);
+                let recent = |samples: &VecDeque<(Instant, f64)>| {
+                    samples
+                        .iter()
+                        .rev()
+                        .take_while(|(at, _)| at.elapsed() <= length)
+                        .map(|(_, value)| *value)
+                        .collect::<Vec<_>>()
+                };
+                let shares = recent(&accepted);
+                EffectiveHashrate {
+                    window,
+                    shares: shares.len(),
+                    effective: shares.iter().sum::<f64>() / span,
+                    measured: recent(&tried).iter().sum::<f64>() / span,
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct EffectiveHashrate {
+    pub window: &'static str,
+    pub shares: usize,
+    /// Hashes per second, as proven by accepted shares
+    pub effective: f64,
+    /// Hashes per second, as reported by the workers
+    pub measured: f64,
+}
+
+impl EffectiveHashrate {
+    pub fn ratio(&self) -> Option<f64> {
+        (self.measured > 0.0).then(|| self.effective / self.measured)
+    }
+}
+
+/// Hashes tried, sampled every second or so independently of the log interval, averaged over `AVERAGE_WINDOWS`
+#[derive(Default)]
+struct HashrateHistory {
+    /// When each sample was taken and the hashes tried since the one before, as far back as the longest window
+    samples: VecDeque<(Instant, u64)>,
+    started: Option<Instant>,
+    hashes: u64,
+    /// Highest and lowest average over the shortest window, leaving out the time nothing was hashed
+    peak: f64,
+    min: Option<f64>,
+}
+
+impl HashrateHistory {
+    fn record(&mut self, now: Instant, hashes: u64, elapsed: Duration) {
+        let started = *self.started.get_or_insert(now - elapsed);
+        self.hashes += hashes;
+        self.samples.push_back((now, hashes));
+        let longest = AVERAGE_WINDOWS[AVERAGE_WINDOWS.len() - 1].1;
+        while self.samples.front().is_some_and(|(at, _)| now - *at > longest) {
+            self.samples.pop_front();
+        }
+        let shortest = AVERAGE_WINDOWS[0].1;
+        if now - started >= shortest {
+            let rate = self.rate(now, shortest);
+            if rate > 0.0 {
+                self.peak = self.peak.max(rate);
+                self.min = Some(self.min.map_or(rate, |min| min.min(rate)));
+            }
+        }
+    }
+
+    /// Windows are cut short while there are fewer samples than their length
+    fn rate(&self, now: Instant, window: Duration) -> f64 {
+        let span = match self.started {
+            Some(started) => window.min(now - started).as_secs_f64().max(f64::EPSILON),
+            None => return 0.0,
+        };
+        let hashes = self.samples.iter().rev().take_while(|(at, _)| now - *at < window).map(|(_, hashes)| hashes);
+        hashes.sum::<u64>() as f64 / span
+    }
+
+    /// When each sample after `since` was taken, and the hashes per second it measured
+    fn rates_since(&self, since: Instant) -> Vec<(Instant, f64)> {
+        let mut previous = match self.started {
+            Some(started) => started,
+            None => return Vec::new(),
+        };
+        let mut rates = Vec::new();
+        for &(at, hashes) in &self.samples {
+            if at > since {
+                rates.push((at, hashes as f64 / (at - previous).as_secs_f64().max(f64::EPSILON)));
+            }
+            previous = at;
+        }
+        rates
+    }
+
+    fn hashes_between(&self, from: Instant, to: Instant) -> u64 {
+        self.samples.iter().filter(|(at, _)| *at > from && *at <= to).map(|(_, hashes)| hashes).sum()
+    }
+
+    fn snapshot(&self, now: Instant) -> Hashrates {
+        let session = self.started.map(|started| (now - started).as_secs_f64().max(f64::EPSILON));
+        Hashrates {
+            windows: AVERAGE_WINDOWS
+                .iter()
+                .map(|&(window, length)| AverageHashrate { window, hashrate: self.rate(now, length) })
+                .collect(),
+            session: session.map(|secs| self.hashes as f64 / secs).unwrap_or_default(),
+            peak: self.peak,
+            min: self.min.unwrap_or_default(),
+        }
+    }
+}
+
+struct JobInfo {
+    id: Option<String>,
+    target: Uint256,
+    nonce_mask: u64,
+    nonce_fixed: u64,
+    received: Instant,
+}
+
+#[derive(Default)]
+struct BlockStats {
+    found: u64,
+    submitted: u64,
+    rejected: u64,
+    dropped_duplicate: u64,
+    recent: VecDeque<FoundBlock>,
+}
+
+/// Statistics of the whole run: shares kept per pool across reconnects, device hashrates, blocks and jobs
+pub struct Statistics {
+    started: Instant,
+    pools: Mutex<HashMap<String, Arc<PoolStats>>>,
+    sessions: Mutex<Vec<Weak<SessionStats>>>,
+    pub hashrate: Arc<HashrateMonitor>,
+    /// In the order the devices were launched
+    devices: Mutex<Vec<DeviceSnapshot>>,
+    blocks: Mutex<BlockStats>,
+    job: Mutex<Option<JobInfo>>,
+    jobs: AtomicU64,
+    connections: Mutex<Vec<String>>,
+    /// Times each pool or node was connected to
+    connects: Mutex<BTreeMap<String, u64>>,
+    /// Averaged hashrates per device, and of all devices together
+    averages: Mutex<HashMap<String, HashrateHistory>>,
+    total_average: Mutex<HashrateHistory>,
+    /// Why the workers are kept from mining, e.g. the schedule
+    held: Mutex<Option<String>>,
+}
+
+impl Default for Statistics {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            pools: Default::default(),
+            sessions: Default::default(),
+            hashrate: Default::default(),
+            devices: Default::default(),
+            blocks: Default::default(),
+            job: Default::default(),
+            jobs: Default::default(),
+            connections: Default::default(),
+            connects: Default::default(),
+            averages: Default::default(),
+            total_average: Default::default(),
+            held: Default::default(),
+        }
+    }
+}
+
+/// Keeps a pool or node listed as connected until dropped
+pub struct Connection<'a> {
+    statistics: &'a Statistics,
+    address: String,
+}
+
+impl Drop for Connection<'_> {
+    fn drop(&mut self) {
+        let mut connections = self.statistics.connections.lock().unwrap();
+        if let Some(position) = connections.iter().position(|address| *address == self.address) {
+            connections.remove(position);
+        }
+    }
+}
+
+/// How a pool or node is known in the statistics: `host:port`, whether given as a URL or not
+pub fn pool_key(address: &str) -> &str {
+    address.split_once("://").map_or(address, |(_, address)| address)
+}
+
+impl Statistics {
+    pub fn connected(&self, address: &str) -> Connection<'_> {
+        let address = pool_key(address).to_string();
+        self.connections.lock().unwrap().push(address.clone());
+        *self.connects.lock().unwrap().entry(address.clone()).or_default() += 1;
+        Connection { statistics: self, address }
+    }
+
+    pub fn add_device(&self, device: &str) {
+        let mut devices = self.devices.lock().unwrap();
+        if !devices.iter().any(|known| known.id == device) {
+            devices.push(DeviceSnapshot { id: device.to_string(), ..Default::default() });
+        }
+    }
+
+    /// Hashes a device tried over the last `duration`
+    pub fn device_hashes(&self, device: &str, hashes: u64, duration: Duration) {
+        self.add_device(device);
+        let mut devices = self.devices.lock().unwrap();
+        if let Some(known) = devices.iter_mut().find(|known| known.id == device) {
+            known.hashes += hashes;
+            known.hashrate = hashes as f64 / duration.as_secs_f64().max(f64::EPSILON);
+        }
+    }
+
+    /// Hashes each device tried since the last sample, `elapsed` ago
+    pub fn sample_hashes(&self, hashes: &[(String, u64)], elapsed: Duration) {
+        let now = Instant::now();
+        let mut averages = self.averages.lock().unwrap();
+        for (device, hashes) in hashes {
+            averages.entry(device.clone()).or_default().record(now, *hashes, elapsed);
+        }
+        let total = hashes.iter().map(|(_, hashes)| hashes).sum();
+        self.total_average.lock().unwrap().record(now, total, elapsed);
+    }
+
+    /// Hashes a device tried between two samples, from the history kept for the averages
+    pub fn sampled_hashes(&self, device: &str, from: Instant, to: Instant) -> u64 {
+        self.averages.lock().unwrap().get(device).map(|history| history.hashes_between(from, to)).unwrap_or_default()
+    }
+
+    /// The samples of a device, or of all devices together, taken after `since`. See `HashrateHistory::rates_since`.
+    pub fn sampled_rates(&self, device: Option<&str>, since: Instant) -> Vec<(Instant, f64)> {
+        match device {
+            Some(device) => {
+                self.averages.lock().unwrap().get(device).map(|history| history.rates_since(since)).unwrap_or_default()
+            }
+            None => self.total_average.lock().unwrap().rates_since(since),
+        }
+    }
+
+    pub fn device_telemetry(&self, device: &str, telemetry: Vec<(&'static str, f64)>) {
+        self.add_device(device);
+        let mut devices = self.devices.lock().unwrap();
+        if let Some(known) = devices.iter_mut().find(|known| known.id == device) {
+            known.telemetry = telemetry.into_iter().map(|(name, value)| (name.to_string(), value)).collect();
+        }
+    }
+
+    pub fn device_workload(&self, device: &str, workload: usize) {
+        self.add_device(device);
+        let mut devices = self.devices.lock().unwrap();
+        if let Some(known) = devices.iter_mut().find(|known| known.id == device) {
+            known.workload = workload;
+        }
+    }
+
+    pub fn device_paused(&self, device: &str, paused: bool) {
+        self.add_device(device);
+        let mut devices = self.devices.lock().unwrap();
+        if let Some(known) = devices.iter_mut().find(|known| known.id == device) {
+            known.paused = paused;
+        }
+    }
+
+    pub fn device_stalled(&self, device: &str, stalled: bool) {
+        self.add_device(device);
+        let mut devices = self.devices.lock().unwrap();
+        if let Some(known) = devices.iter_mut().find(|known| known.id == device) {
+            known.stalled = stalled;
+        }
+    }
+
+    pub fn device_restarted(&self, device: &str) {
+        self.add_device(device);
+        let mut devices = self.devices.lock().unwrap();
+        if let Some(known) = devices.iter_mut().find(|known| known.id == device) {
+            known.restarts += 1;
+        }
+    }
+
+    pub fn held(&self, reason: Option<&str>) {
+        *self.held.lock().unwrap() = reason.map(str::to_string);
+    }
+
+    /// A block was handed to the node
+    pub fn block_submitted(&self) {
+        self.blocks.lock().unwrap().submitted += 1;
+    }
+
+    /// The node refused a submitted block
+    pub fn block_rejected(&self) {
+        self.blocks.lock().unwrap().rejected += 1;
+    }
+
+    /// A block already submitted for the same template was found again and not sent
+    pub fn block_dropped_duplicate(&self) {
+        self.blocks.lock().unwrap().dropped_duplicate += 1;
+    }
+
+    pub fn block_found(&self, hash: String) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        let mut blocks = self.blocks.lock().unwrap();
+        blocks.found += 1;
+        if blocks.recent.len() == RECENT_BLOCKS {
+            blocks.recent.pop_front();
+        }
+        blocks.recent.push_back(FoundBlock { hash, timestamp });
+    }
+
+    /// The job handed to the workers. Full blocks from a node have no job id.
+    pub fn new_job(&self, id: Option<String>, target: Uint256, nonce_mask: u64, nonce_fixed: u64) {
+        self.jobs.fetch_add(1, Ordering::SeqCst);
+        *self.job.lock().unwrap() = Some(JobInfo { id, target, nonce_mask, nonce_fixed, received: Instant::now() });
+    }
+
+    pub fn summary(&self) -> SummarySnapshot {
+        let devices = self.devices();
+        SummarySnapshot {
+            version: env!("CARGO_PKG_VERSION"),
+            uptime_secs: self.started.elapsed().as_secs(),
+            connections: self.connections.lock().unwrap().clone(),
+            hashrate: devices.iter().map(|device| device.hashrate).sum(),
+            hashrates: self.total_average.lock().unwrap().snapshot(Instant::now()),
+            devices: devices.len(),
+            held: self.held.lock().unwrap().clone(),
+            shares: self.snapshot().lifetime,
+            blocks_found: self.blocks.lock().unwrap().found,
+        }
+    }
+
+    pub fn devices(&self) -> Vec<DeviceSnapshot> {
+        let now = Instant::now();
+        let averages = self.averages.lock().unwrap();
+        let mut devices = self.devices.lock().unwrap().clone();
+        for device in &mut devices {
+            if let Some(history) = averages.get(&device.id) {
+                device.hashrates = history.snapshot(now);
+            }
+        }
+        devices
+    }
+
+    pub fn blocks(&self) -> BlocksSnapshot {
+        let blocks = self.blocks.lock().unwrap();
+        BlocksSnapshot {
+            found: blocks.found,
+            submitted: blocks.submitted,
+            rejected: blocks.rejected,
+            dropped_duplicate: blocks.dropped_duplicate,
+            recent: blocks.recent.iter().cloned().collect(),
+        }
+    }
+
+    /// Pools and nodes connected to, with the number of times each was connected to again
+    pub fn reconnects(&self) -> Vec<(String, u64)> {
+        self.connects.lock().unwrap().iter().map(|(address, count)| (address.clone(), count - 1)).collect()
+    }
+
+    pub fn job(&self) -> JobSnapshot {
+        let job = self.job.lock().unwrap();
+        JobSnapshot {
+            jobs: self.jobs.load(Ordering::SeqCst),
+            current: job.as_ref().map(|job| CurrentJob {
+                id: job.id.clone(),
+                target: hex::encode(job.target.to_be_bytes()),
+                nonce_mask: format!("{:016x}", job.nonce_mask),
+                nonce_fixed: format!("{:016x}", job.nonce_fixed),
+                age_secs: job.received.elapsed().as_secs_f64(),
+            }),
+        }
+    }
+
+    /// Starts counting a new connection to `pool`. Its shares count towards the pool totals as well.
+    pub fn start_session(&self, pool: &str) -> Arc<SessionStats> {
+        let pool = pool_key(pool);
+        let pool_stats = self.pools.lock().unwrap().entry(pool.to_string()).or_default().clone();
+        let session = Arc::new(SessionStats {
+            pool: pool.to_string(),
+            started: Instant::now(),
+            shares: Default::default(),
+            pool_stats,
+            hashrate: self.hashrate.clone(),
+            pending: Default::default(),
+        });
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|session| session.strong_count() > 0);
+        sessions.push(Arc::downgrade(&session));
+        session
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let mut pools = self.pools.lock().unwrap().iter().map(|(pool, stats)| stats.snapshot(pool)).collect::<Vec<_>>();
+        pools.sort_by(|a, b| a.pool.cmp(&b.pool));
+        let mut lifetime = ShareCounts::default();
+        pools.iter().for_each(|pool| lifetime += pool.shares);
+        let sessions =
+            self.sessions.lock().unwrap().iter().filter_map(Weak::upgrade).map(|session| session.snapshot()).collect();
+        StatsSnapshot { lifetime, pools, sessions, hashrate: self.hashrate.snapshot() }
+    }
+}
+
+impl PoolStats {
+    fn snapshot(&self, pool: &str) -> PoolSnapshot {
+        PoolSnapshot {
+            pool: pool.to_string(),
+            shares: self.shares.snapshot(),
+            latency: self.latency.lock().unwrap().snapshot(),
+        }
+    }
+}
+
+/// Shares of a single pool connection. Stratum ids restart with every connection,
+/// so shares still pending when it closes are counted as lost.
+pub struct SessionStats {
+    pool: String,
+    started: Instant,
+    shares: ShareCounters,
+    pool_stats: Arc<PoolStats>,
+    hashrate: Arc<HashrateMonitor>,
+    pending: Mutex<HashMap<u32, (String, Instant)>>,
+}
+
+impl SessionStats {
+    pub fn record(&self, event: ShareEvent) {
+        self.add(event, 1);
+    }
+
+    fn add(&self, event: ShareEvent, count: u64) {
+        self.shares.add(event, count);
+        self.pool_stats.shares.add(event, count);
+    }
+
+    /// Counts an accepted share towards the effective hashrate as well
+    pub fn accepted(&self, work: f64) {
+        self.record(ShareEvent::Accepted);
+        self.hashrate.accepted(work);
+    }
+
+    pub fn submitted(&self, id: u32, job_id: String) {
+        self.pending.lock().unwrap().insert(id, (job_id, Instant::now()));
+    }
+
+    /// Removes an answered share, records its round trip and returns its job id
+    pub fn answer(&self, id: u32) -> Option<String> {
+        let (job_id, submitted) = self.pending.lock().unwrap().remove(&id)?;
+        self.pool_stats.latency.lock().unwrap().record(submitted.elapsed());
+        Some(job_id)
+    }
+
+    /// Drops shares left unanswered for longer than `timeout`, counting them as lost
+    pub fn expire(&self, timeout: Duration) -> usize {
+        let mut pending = self.pending.lock().unwrap();
+        let before = pending.len();
+        pending.retain(|_, (_, submitted)| submitted.elapsed() <= timeout);
+        let expired = before - pending.len();
+        drop(pending);
+        self.add(ShareEvent::Lost, expired as u64);
+        expired
+    }
+
+    pub fn snapshot(&self) -> SessionSnapshot {
+        SessionSnapshot {
+            pool: self.pool.clone(),
+            connected_secs: self.started.elapsed().as_secs(),
+            shares: self.shares.snapshot(),
+            pending: self.pending.lock().unwrap().len(),
+        }
+    }
+
+    /// Totals of the pool this session is connected to
+    pub fn pool_snapshot(&self) -> PoolSnapshot {
+        self.pool_stats.snapshot(&self.pool)
+    }
+}
+
+impl Drop for SessionStats {
+    fn drop(&mut self) {
+        let lost = self.pending.get_mut().map(|pending| pending.len()).unwrap_or_default();
+        self.pool_stats.shares.add(ShareEvent::Lost, lost as u64);
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StatsSnapshot {
+    pub lifetime: ShareCounts,
+    pub pools: Vec<PoolSnapshot>,
+    pub sessions: Vec<SessionSnapshot>,
+    pub hashrate: Vec<EffectiveHashrate>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SummarySnapshot {
+    pub version: &'static str,
+    pub uptime_secs: u64,
+    /// Pools or nodes currently connected to
+    pub connections: Vec<String>,
+    /// Hashes per second, as reported by the workers over the last logging interval
+    pub hashrate: f64,
+    pub hashrates: Hashrates,
+    pub devices: usize,
+    /// Why no device is mining, if they are all kept from it
+    pub held: Option<String>,
+    pub shares: ShareCounts,
+    pub blocks_found: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Hashrates {
+    pub windows: Vec<AverageHashrate>,
+    /// Average since the first sample
+    pub session: f64,
+    pub peak: f64,
+    pub min: f64,
+}
+
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct AverageHashrate {
+    pub window: &'static str,
+    pub hashrate: f64,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DeviceSnapshot {
+    pub id: String,
+    /// Hashes per second over the last logging interval
+    pub hashrate: f64,
+    pub hashrates: Hashrates,
+    pub hashes: u64,
+    /// Nonces per kernel run
+    pub workload: usize,
+    pub paused: bool,
+    /// Stuck in a kernel run, which only a new process gets the device back from
+    pub stalled: bool,
+    /// Times the worker was started again after crashing or getting stuck
+    pub restarts: u64,
+    /// Readings the plugin provides, e.g. `temperature_celsius`
+    pub telemetry: BTreeMap<String, f64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct FoundBlock {
+    pub hash: String,
+    /// Milliseconds since the unix epoch
+    pub timestamp: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BlocksSnapshot {
+    pub found: u64,
+    pub submitted: u64,
+    pub rejected: u64,
+    pub dropped_duplicate: u64,
+    pub recent: Vec<FoundBlock>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct JobSnapshot {
+    /// Jobs handed to the workers since start
+    pub jobs: u64,
+    pub current: Option<CurrentJob>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CurrentJob {
+    pub id: Option<String>,
+    pub target: String,
+    pub nonce_mask: String,
+    pub nonce_fixed: String,
+    pub age_secs: f64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PoolSnapshot {
+    pub pool: String,
+    pub shares: ShareCounts,
+    pub latency: LatencySnapshot,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SessionSnapshot {
+    pub pool: String,
+    pub connected_secs: u64,
+    pub shares: ShareCounts,
+    pub pending: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HashrateHistory, HashrateMonitor, ShareEvent, ShareLatency, Statistics, LATENCY_SAMPLES};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_share_latency() {
+        let mut latency = ShareLatency::default();
+        assert_eq!(latency.min(), None);
+        assert_eq!(latency.avg(), None);
+        assert_eq!(latency.p95(), None);
+
+        (1..=100).rev().for_each(|ms| latency.record(Duration::from_millis(ms)));
+        assert_eq!(latency.min(), Some(Duration::from_millis(1)));
+        assert_eq!(latency.avg(), Some(Duration::from_micros(50_500)));
+        assert_eq!(latency.p95(), Some(Duration::from_millis(95)));
+
+        (0..LATENCY_SAMPLES).for_each(|_| latency.record(Duration::from_millis(200)));
+        assert_eq!(latency.min(), Some(Duration::from_millis(200)));
+        assert_eq!(latency.p95(), Some(Duration::from_millis(200)));
+    }
+
+    #[test]
+    fn test_statistics() {
+        let statistics = Statistics::default();
+        let session = statistics.start_session("pool-a:5555");
+        session.submitted(1, "job".into());
+        session.submitted(2, "job".into());
+        assert_eq!(session.answer(1), Some("job".into()));
+        assert_eq!(session.answer(1), None);
+        session.record(ShareEvent::Accepted);
+        drop(session);
+
+        let session = statistics.start_session("pool-a:5555");
+        session.record(ShareEvent::Stale);
+        let other = statistics.start_session("pool-b:5555");
+        other.record(ShareEvent::Accepted);
+
+        let snapshot = statistics.snapshot();
+        assert_eq!(snapshot.sessions.len(), 2);
+        assert_eq!(snapshot.sessions[0].shares.accepted, 0);
+        assert_eq!(snapshot.sessions[0].shares.stale, 1);
+        assert_eq!(snapshot.pools[0].pool, "pool-a:5555");
+        // The share left pending by the first connection is lost
+        assert_eq!((snapshot.pools[0].shares.accepted, snapshot.pools[0].shares.lost), (1, 1));
+        assert_eq!(snapshot.pools[1].shares.accepted, 1);
+        assert_eq!((snapshot.lifetime.accepted, snapshot.lifetime.stale, snapshot.lifetime.lost), (2, 1, 1));
+    }
+
+    #[test]
+    fn test_hashrate_monitor() {
+        let monitor = HashrateMonitor::default();
+        assert!(monitor.snapshot().iter().all(|window| window.shares == 0 && window.ratio().is_none()));
+
+        monitor.tried(1000);
+        monitor.accepted(400.0);
+        monitor.accepted(400.0);
+        let snapshot = monitor.snapshot();
+        assert_eq!(snapshot.len(), 3);
+        // Just started, so every window covers the same second
+        assert!(snapshot.iter().all(|window| window.shares == 2));
+        assert!(snapshot.iter().all(|window| (window.ratio().unwrap() - 0.8).abs() < 1e-9));
+    }
+
+    #[test]
+    fn test_hashrate_history() {
+        let mut history = HashrateHistory::default();
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        for secs in 1..=60 {
+            // 1000 hash/s for 30 seconds, then 3000 hash/s
+            history.record(at(secs), if secs <= 30 { 1000 } else { 3000 }, Duration::from_secs(1));
+        }
+        let rates = history.snapshot(at(60));
+        let windows = rates.windows.iter().map(|window| (window.window, window.hashrate)).collect::<Vec<_>>();
+        assert_eq!(windows, [("10s", 3000.0), ("60s", 2000.0), ("15m", 2000.0)]);
+        assert_eq!((rates.session, rates.peak, rates.min), (2000.0, 3000.0, 1000.0));
+        assert_eq!(history.hashes_between(at(25), at(35)), 5 * 1000 + 5 * 3000);
+        assert_eq!(history.rates_since(at(58)), [(at(59), 3000.0), (at(60), 3000.0)]);
+
+        history.record(at(17 * 60), 0, Duration::from_secs(1));
+        assert_eq!(history.samples.len(), 1);
+        assert_eq!(history.snapshot(at(17 * 60)).min, 1000.0);
+    }
+}
@@ -60,6 +60,14 @@ impl Uint256 {
         out
     }
 
+    /// Creates big integer value from a byte slice using
+    /// big-endian encoding
+    #[inline(always)]
+    pub fn from_be_bytes(mut bytes: [u8; 32]) -> Uint256 {
+        bytes.reverse();
+        Self::from_le_bytes(bytes)
+    }
+
     #[inline(always)]
     pub fn to_be_bytes(self) -> [u8; 32] {
         let mut out = [0u8; 32];
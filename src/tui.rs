@@ -0,0 +1,271 @@
+//! Full-screen dashboard for `--tui`, drawn from the same statistics the log lines and the API report.
+//! Log records are shown in the dashboard while it is open, `q` goes back to the plain log.
+
+use log::{error, info};
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph, Row, Table};
+use ratatui::{Frame, Terminal};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, IsTerminal};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::logger;
+use crate::miner::MinerManager;
+use crate::shutdown;
+use crate::stats::Statistics;
+
+const REFRESH_RATE: Duration = Duration::from_secs(1);
+/// Hashrate samples kept per device for the trend column, one per hashrate log line
+const HISTORY: usize = 40;
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Opens the dashboard on its own thread, unless stdout is not a terminal and the plain log is kept
+pub fn spawn(statistics: Arc<Statistics>) {
+    if !io::stdout().is_terminal() {
+        info!("stdout is not a terminal, logging instead of showing the dashboard");
+        return;
+    }
+    std::thread::spawn(move || {
+        if let Err(e) = run(&statistics) {
+            error!("Dashboard closed: {}", e);
+        }
+    });
+}
+
+/// Raw mode and the alternate screen, restored when dropped
+struct Screen;
+
+impl Screen {
+    fn enter() -> io::Result<Self> {
+        let screen = Screen;
+        enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+        logger::capture(true);
+        Ok(screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        logger::capture(false);
+        _ = execute!(io::stdout(), LeaveAlternateScreen);
+        _ = disable_raw_mode();
+    }
+}
+
+fn run(statistics: &Statistics) -> io::Result<()> {
+    let screen = Screen::enter()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+    let mut dashboard = Dashboard::default();
+    loop {
+        dashboard.sample(statistics);
+        terminal.draw(|frame| dashboard.draw(frame, statistics))?;
+        if !event::poll(REFRESH_RATE)? {
+            continue;
+        }
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => break,
+            // Raw mode keeps Ctrl-C from raising SIGINT
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                drop(screen);
+                shutdown::request();
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+    drop(screen);
+    info!("Dashboard closed, logging to the terminal");
+    Ok(())
+}
+
+#[derive(Default)]
+struct Dashboard {
+    /// Per device: the last (hashes, hashrate) seen, and the hashrates since
+    history: HashMap<String, ((u64, u64), VecDeque<f64>)>,
+}
+
+impl Dashboard {
+    /// Records a device's hashrate whenever the miner logs a new one
+    fn sample(&mut self, statistics: &Statistics) {
+        for device in statistics.devices() {
+            let (seen, history) = self.history.entry(device.id).or_default();
+            let current = (device.hashes, device.hashrate.to_bits());
+            if device.hashes == 0 || *seen == current {
+                continue;
+            }
+            *seen = current;
+            if history.len() == HISTORY {
+                history.pop_front();
+            }
+            history.push_back(device.hashrate);
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame, statistics: &Statistics) {
+        let summary = statistics.summary();
+        let devices = statistics.devices();
+        let shares = statistics.snapshot();
+        let blocks = statistics.blocks();
+        let job = statistics.job();
+
+        let [header, devices_area, counters, events] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(devices.len() as u16 + 3),
+            Constraint::Length(6),
+            Constraint::Min(3),
+        ])
+        .areas(frame.area());
+
+        let connection = match summary.connections.is_empty() {
+            true => Span::styled("not connected", Style::new().fg(Color::Red)),
+            false => Span::styled(summary.connections.join(", "), Style::new().fg(Color::Green)),
+        };
+        let job_age = match &job.current {
+            Some(current) => format!("{:.0}s ago", current.age_secs),
+            None => "none yet".to_string(),
+        };
+        let status = Line::from(vec![
+            "Pool: ".bold(),
+            connection,
+            "  Job: ".bold(),
+            job_age.into(),
+            "  Hashrate: ".bold(),
+            hashrate(summary.hashrate).into(),
+            "  Uptime: ".bold(),
+            uptime(summary.uptime_secs).into(),
+        ]);
+        let title = format!(" karlsen-miner {} ", summary.version);
+        frame.render_widget(Paragraph::new(status).block(Block::bordered().title(title)), header);
+
+        let rows = devices.iter().map(|device| {
+            let reading = |name: &str, unit: &str| {
+                device.telemetry.get(name).map(|value| format!("{:.0}{}", value, unit)).unwrap_or_default()
+            };
+            let trend = self.history.get(&device.id).map(|(_, history)| sparkline(history)).unwrap_or_default();
+            Row::new(vec![
+                device.id.clone(),
+                hashrate(device.hashrate),
+                trend,
+                reading("temperature_celsius", "°C"),
+                reading("power_watts", " W"),
+                reading("fan_speed_percent", "%"),
+            ])
+        });
+        let widths = [
+            Constraint::Fill(2),
+            Constraint::Length(16),
+            Constraint::Length(HISTORY as u16),
+            Constraint::Length(6),
+            Constraint::Length(7),
+            Constraint::Length(5),
+        ];
+        let table = Table::new(rows, widths)
+            .header(Row::new(["Device", "Hashrate", "Trend", "Temp", "Power", "Fan"]).bold())
+            .block(Block::bordered().title(" Devices "));
+        frame.render_widget(table, devices_area);
+
+        let [shares_area, blocks_area, effective_area] =
+            Layout::horizontal([Constraint::Fill(1), Constraint::Fill(1), Constraint::Fill(2)]).areas(counters);
+        let lifetime = shares.lifetime;
+        let share_lines = vec![
+            Line::from(format!("Accepted: {}", lifetime.accepted)),
+            Line::from(format!("Stale: {}  Low difficulty: {}", lifetime.stale, lifetime.low_difficulty)),
+            Line::from(format!("Duplicate: {}  Lost: {}", lifetime.duplicate, lifetime.lost)),
+        ];
+        frame.render_widget(Paragraph::new(share_lines).block(Block::bordered().title(" Shares ")), shares_area);
+        let block_lines = vec![
+            Line::from(format!("Found: {}", blocks.found)),
+            Line::from(format!("Submitted: {}", blocks.submitted)),
+            Line::from(format!("Rejected: {}  Duplicate: {}", blocks.rejected, blocks.dropped_duplicate)),
+        ];
+        frame.render_widget(Paragraph::new(block_lines).block(Block::bordered().title(" Blocks ")), blocks_area);
+        let effective_lines = shares
+            .hashrate
+            .iter()
+            .map(|window| match window.ratio() {
+                Some(ratio) => format!(
+                    "{}: {} ({:.0}% of measured, {} shares)",
+                    window.window,
+                    hashrate(window.effective),
+                    ratio * 100.0,
+                    window.shares
+                ),
+                None => format!("{}: {} ({} shares)", window.window, hashrate(window.effective), window.shares),
+            })
+            .map(Line::from)
+            .collect::<Vec<_>>();
+        let effective = Paragraph::new(effective_lines).block(Block::bordered().title(" Effective hashrate "));
+        frame.render_widget(effective, effective_area);
+
+        let recent = logger::recent(events.height.saturating_sub(2) as usize);
+        let recent = recent.into_iter().map(Line::from).collect::<Vec<_>>();
+        frame.render_widget(Paragraph::new(recent).block(Block::bordered().title(" Events ")), events);
+    }
+}
+
+fn hashrate(rate: f64) -> String {
+    let (rate, suffix) = MinerManager::hash_suffix(rate);
+    format!("{:.2} {}", rate, suffix)
+}
+
+fn uptime(secs: u64) -> String {
+    format!("{}h {:02}m {:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+fn sparkline(history: &VecDeque<f64>) -> String {
+    let max = history.iter().copied().fold(0.0, f64::max);
+    history
+        .iter()
+        .map(|&rate| match max > 0.0 {
+            true => SPARKS[(rate / max * (SPARKS.len() - 1) as f64).round() as usize],
+            false => SPARKS[0],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sparkline, Dashboard};
+    use crate::stats::Statistics;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use std::time::Duration;
+
+    #[test]
+    fn test_dashboard() {
+        assert_eq!(sparkline(&[0.0, 4.0, 8.0].into()), "▁▅█");
+
+        let statistics = Statistics::default();
+        statistics.device_hashes("#0 (Test GPU)", 3000, Duration::from_secs(3));
+        statistics.device_telemetry("#0 (Test GPU)", vec![("temperature_celsius", 64.0)]);
+        statistics.block_found("ab".into());
+        let mut dashboard = Dashboard::default();
+        dashboard.sample(&statistics);
+        dashboard.sample(&statistics);
+        statistics.device_hashes("#0 (Test GPU)", 6000, Duration::from_secs(3));
+        dashboard.sample(&statistics);
+        assert_eq!(dashboard.history["#0 (Test GPU)"].1, [1000.0, 2000.0]);
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 24)).unwrap();
+        terminal.draw(|frame| dashboard.draw(frame, &statistics)).unwrap();
+        let screen = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect::<String>();
+        assert!(screen.contains("#0 (Test GPU)"));
+        assert!(screen.contains("2.00 Khash/s"));
+        assert!(screen.contains("▅█"));
+        assert!(screen.contains("64°C"));
+        assert!(screen.contains("not connected"));
+        assert!(screen.contains("Found: 1"));
+    }
+}
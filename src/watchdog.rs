@@ -0,0 +1,247 @@
+//! Stall watchdog, enabled with `--watchdog-timeout`. Devices that hash nothing, jobs that stop coming and
+//! connections that stay down are each answered with their configured action, so a frozen rig recovers on its own.
+
+use log::{error, info, warn};
+use std::collections::HashMap;
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::cli::Opt;
+use crate::config::Settings;
+use crate::miner::Controls;
+use crate::shutdown;
+use crate::stats::Statistics;
+use crate::Error;
+
+/// Exit code of the `exit` action, so a supervisor such as systemd or HiveOS can tell a stall from a crash
+pub const STALL_EXIT_CODE: i32 = 75;
+const CHECK_RATE: Duration = Duration::from_secs(5);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WatchdogAction {
+    /// Starts the device's worker again, only for devices
+    Restart,
+    Reconnect,
+    Exit,
+    /// Runs `--watchdog-hook`
+    Hook,
+}
+
+impl FromStr for WatchdogAction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "restart" => Ok(Self::Restart),
+            "reconnect" => Ok(Self::Reconnect),
+            "exit" => Ok(Self::Exit),
+            "hook" => Ok(Self::Hook),
+            _ => Err("Unknown watchdog action, expected restart, reconnect, exit or hook".into()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Stall {
+    /// A device that is not paused or held tried no hashes although there is a fresh job
+    Device(String),
+    /// No new job from the pool or node
+    Job,
+    Connection,
+}
+
+impl Stall {
+    fn reason(&self) -> &'static str {
+        match self {
+            Stall::Device(_) => "device",
+            Stall::Job => "job",
+            Stall::Connection => "connection",
+        }
+    }
+}
+
+pub struct Watchdog {
+    timeout: Duration,
+    device_action: WatchdogAction,
+    job_action: WatchdogAction,
+    connection_action: WatchdogAction,
+    hook: Option<String>,
+    /// Per device: the hashes last seen, and since when
+    devices: HashMap<String, (u64, Instant)>,
+    /// When the last stale job was acted on
+    job_acted: Option<Instant>,
+    /// Since when the connection has been down, or was last acted on
+    connection: Instant,
+}
+
+impl Watchdog {
+    pub fn new(opt: &Opt, now: Instant) -> Self {
+        let mut watchdog = Self {
+            timeout: Duration::ZERO,
+            device_action: WatchdogAction::Restart,
+            job_action: WatchdogAction::Reconnect,
+            connection_action: WatchdogAction::Exit,
+            hook: None,
+            devices: HashMap::new(),
+            job_acted: None,
+            connection: now,
+        };
+        watchdog.configure(opt);
+        watchdog
+    }
+
+    /// Follows reloaded settings without losing track of the stalls
+    fn configure(&mut self, opt: &Opt) {
+        self.timeout = Duration::from_secs(opt.watchdog_timeout);
+        self.device_action = opt.watchdog_device_action;
+        self.job_action = opt.watchdog_job_action;
+        self.connection_action = opt.watchdog_connection_action;
+        self.hook = opt.watchdog_hook.clone();
+    }
+
+    /// The stalls lasting `timeout` at `now` and what to do about them. Each is reported once per `timeout`.
+    fn check(&mut self, statistics: &Statistics, now: Instant) -> Vec<(Stall, WatchdogAction)> {
+        let mut stalls = Vec::new();
+        let summary = statistics.summary();
+        let connected = !summary.connections.is_empty();
+        let job_age = statistics.job().current.map(|job| Duration::from_secs_f64(job.age_secs));
+        let fresh_job = connected && summary.held.is_none() && job_age.is_some_and(|age| age < self.timeout);
+
+        if connected {
+            self.connection = now;
+        } else if now - self.connection >= self.timeout {
+            self.connection = now;
+            stalls.push((Stall::Connection, self.connection_action));
+        }
+        let job_stalled = connected && job_age.is_some_and(|age| age >= self.timeout);
+        if job_stalled && self.job_acted.is_none_or(|acted| now - acted >= self.timeout) {
+            self.job_acted = Some(now);
+            stalls.push((Stall::Job, self.job_action));
+        }
+        for device in statistics.devices() {
+            let (hashes, since) = self.devices.entry(device.id.clone()).or_insert((device.hashes, now));
+            let watched = device.stalled || !device.paused && fresh_job;
+            if !watched || device.hashes != *hashes {
+                *hashes = device.hashes;
+                *since = now;
+            } else if now - *since >= self.timeout {
+                *since = now;
+                // A worker stuck in a kernel run can't be restarted, only a new process gets the device back
+                let action = match self.device_action {
+                    WatchdogAction::Restart if device.stalled => WatchdogAction::Exit,
+                    action => action,
+                };
+                stalls.push((Stall::Device(device.id), action));
+            }
+        }
+        stalls
+    }
+
+    fn act(&self, stall: &Stall, action: WatchdogAction, controls: &Controls, settings: &Settings) {
+        let what = match stall {
+            Stall::Device(device) => format!("{} hashed nothing", device),
+            Stall::Job => "No new job arrived".to_string(),
+            Stall::Connection => "The pool or node stayed disconnected".to_string(),
+        };
+        warn!("Watchdog: {} for {}s, action: {:?}", what, self.timeout.as_secs(), action);
+        match (action, stall) {
+            (WatchdogAction::Restart, Stall::Device(device)) => {
+                if let Err(e) = controls.restart(std::slice::from_ref(device)) {
+                    error!("Watchdog: failed restarting {}: {}", device, e);
+                }
+            }
+            (WatchdogAction::Restart | WatchdogAction::Reconnect, _) => {
+                let opt = settings.borrow().clone();
+                _ = settings.send(opt);
+            }
+            (WatchdogAction::Exit, _) => {
+                error!("Watchdog: exiting with code {}", STALL_EXIT_CODE);
+                shutdown::request_exit(STALL_EXIT_CODE);
+            }
+            (WatchdogAction::Hook, _) => self.run_hook(stall),
+        }
+    }
+
+    /// Runs the hook without waiting for it, with the stall in `KARLSEN_WATCHDOG_REASON` and `KARLSEN_WATCHDOG_DEVICE`
+    fn run_hook(&self, stall: &Stall) {
+        let hook = match &self.hook {
+            Some(hook) => hook,
+            None => return,
+        };
+        let mut command = match cfg!(target_os = "windows") {
+            true => Command::new("cmd"),
+            false => Command::new("sh"),
+        };
+        command.arg(if cfg!(target_os = "windows") { "/C" } else { "-c" }).arg(hook);
+        command.env("KARLSEN_WATCHDOG_REASON", stall.reason());
+        if let Stall::Device(device) = stall {
+            command.env("KARLSEN_WATCHDOG_DEVICE", device);
+        }
+        match command.spawn() {
+            Ok(mut child) => {
+                std::thread::spawn(move || match child.wait() {
+                    Ok(status) if status.success() => info!("Watchdog hook finished"),
+                    Ok(status) => warn!("Watchdog hook failed: {}", status),
+                    Err(e) => warn!("Watchdog hook failed: {}", e),
+                });
+            }
+            Err(e) => error!("Watchdog: failed running hook {}: {}", hook, e),
+        }
+    }
+}
+
+/// Checks for stalls until the miner exits. The actions follow the settings the miner currently runs with.
+pub async fn run(statistics: Arc<Statistics>, controls: Arc<Controls>, settings: Settings) {
+    let mut watchdog = Watchdog::new(&settings.borrow(), Instant::now());
+    let mut ticker = tokio::time::interval(CHECK_RATE);
+    loop {
+        ticker.tick().await;
+        watchdog.configure(&settings.borrow());
+        if watchdog.timeout.is_zero() {
+            continue;
+        }
+        for (stall, action) in watchdog.check(&statistics, Instant::now()) {
+            watchdog.act(&stall, action, &controls, &settings);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Stall, Watchdog, WatchdogAction};
+    use crate::cli::Opt;
+    use crate::stats::Statistics;
+    use clap::Parser;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_watchdog() {
+        let opt = Opt::parse_from(["karlsen-miner", "-a", "karlsen:miner", "--watchdog-timeout", "60"]);
+        let statistics = Statistics::default();
+        let start = Instant::now();
+        let mut watchdog = Watchdog::new(&opt, start);
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        statistics.add_device("#0 (Test GPU)");
+        assert!(watchdog.check(&statistics, at(30)).is_empty());
+        assert_eq!(watchdog.check(&statistics, at(60)), [(Stall::Connection, WatchdogAction::Exit)]);
+        assert!(watchdog.check(&statistics, at(90)).is_empty());
+
+        let _connection = statistics.connected("pool:5555");
+        statistics.new_job(Some("1".into()), Default::default(), u64::MAX, 0);
+        statistics.device_hashes("#0 (Test GPU)", 3000, Duration::from_secs(3));
+        assert!(watchdog.check(&statistics, at(100)).is_empty());
+        let device = Stall::Device("#0 (Test GPU)".into());
+        assert_eq!(watchdog.check(&statistics, at(160)), [(device, WatchdogAction::Restart)]);
+
+        statistics.device_paused("#0 (Test GPU)", true);
+        assert!(watchdog.check(&statistics, at(220)).is_empty());
+
+        let device = Stall::Device("#0 (Test GPU)".into());
+        statistics.device_stalled("#0 (Test GPU)", true);
+        assert!(watchdog.check(&statistics, at(250)).is_empty());
+        assert_eq!(watchdog.check(&statistics, at(310)), [(device, WatchdogAction::Exit)]);
+    }
+}